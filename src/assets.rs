@@ -0,0 +1,29 @@
+use comfy::*;
+
+/// Embedded so that the same assets are available natively and in the WASM build
+static ASSET_DIR: include_dir::Dir<'_> = include_dir::include_dir!("$CARGO_MANIFEST_DIR/assets");
+
+/// Every file under `assets/<directory>` with the given extension, named by its
+/// path relative to that directory without the extension, e.g. `enemies/walker`
+pub fn embedded_assets(directory: &str, extension: &str) -> Vec<(String, &'static [u8])> {
+    let Some(root) = ASSET_DIR.get_dir(directory) else {
+        return vec![];
+    };
+    let mut result = vec![];
+    let mut dirs = vec![root];
+    while let Some(dir) = dirs.pop() {
+        dirs.extend(dir.dirs());
+        for file in dir.files() {
+            let path = file.path();
+            if path.extension().is_none_or(|other| other != extension) {
+                continue;
+            }
+            let without_extension = path.with_extension("");
+            let Ok(name) = without_extension.strip_prefix(directory) else {
+                continue;
+            };
+            result.push((name.to_string_lossy().replace('\\', "/"), file.contents()));
+        }
+    }
+    result
+}
@@ -0,0 +1,228 @@
+use comfy::{
+    kira::{sound::Region, tween::Tween, Volume},
+    *,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+pub const AUDIO_SETTINGS_KEY: &str = "audio_settings";
+
+const SOUNDS_DIR: &str = "sounds";
+const MUSIC: &str = "music/main";
+const ENEMY_KILLED: &str = "enemy_killed";
+const PLAYER_DAMAGED: &str = "player_damaged";
+const LOW_HP: &str = "low_hp";
+const INTEREST: &str = "interest";
+const GAME_OVER: &str = "game_over";
+
+/// Fraction of the player's HP under which the warning plays
+const LOW_HP_FRACTION: f32 = 0.25;
+/// Number of enemies at which the music plays at full volume
+const MAXIMUM_INTENSITY_ENEMIES: f32 = 20.;
+/// Music volume with no enemies, relative to full intensity
+const MINIMUM_INTENSITY: f32 = 0.3;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master_volume: f32,
+    pub effects_volume: f32,
+    pub music_volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.,
+            effects_volume: 0.8,
+            music_volume: 0.5,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn load() -> Result<Self> {
+        Ok(storage()?
+            .read_json(AUDIO_SETTINGS_KEY)?
+            .unwrap_or_default())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        storage()?.write_json(AUDIO_SETTINGS_KEY, self)
+    }
+}
+
+/// Plays sound effects for gameplay events and the background music.
+/// Kept across runs, so the music does not restart with every run.
+#[derive(Default)]
+pub struct Audio {
+    pub settings: AudioSettings,
+    /// Comfy falls back to an error sound for unknown names, so only these are played
+    loaded: HashSet<String>,
+    is_music_playing: bool,
+    /// Music volume last handed to the playing music, if any
+    music_volume: Option<f32>,
+    is_low_hp_warned: bool,
+}
+
+impl Audio {
+    pub fn new(settings: AudioSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
+    /// Registers every WAV and OGG file under `assets/sounds` by its path
+    /// without the extension, e.g. `traps/simple`. Returns why the files which
+    /// could not be loaded were skipped.
+    pub fn load_sounds(&mut self) -> Vec<String> {
+        let mut failures = Vec::new();
+        let files = embedded_assets(SOUNDS_DIR, "wav")
+            .into_iter()
+            .chain(embedded_assets(SOUNDS_DIR, "ogg"));
+        for (name, bytes) in files {
+            let settings = match name == MUSIC {
+                true => StaticSoundSettings::new().loop_region(Region::default()),
+                false => StaticSoundSettings::new(),
+            };
+            // Comfy panics on sounds it cannot decode, so broken files are skipped
+            if let Err(err) =
+                StaticSoundData::from_cursor(std::io::Cursor::new(bytes.to_vec()), settings)
+            {
+                failures.push(format!("Failed to load sound {}: {}", name, err));
+                continue;
+            }
+            load_sound_from_bytes(&name, bytes, settings);
+            self.loaded.insert(name);
+        }
+        set_master_volume(self.settings.master_volume as f64);
+        failures
+    }
+
+    /// Comfy ignores settings when playing, so the volume is stored on the sound itself
+    fn play(&self, name: &str, volume: f32) {
+        if !self.loaded.contains(name) {
+            return;
+        }
+        let sound = sound_id(name);
+        if let Some(data) = ASSETS.borrow().sounds.lock().get_mut(&sound) {
+            data.settings.volume = Volume::Amplitude(volume as f64).into();
+        }
+        play_sound_id(sound);
+    }
+
+    fn play_effect(&self, name: &str) {
+        self.play(name, self.settings.effects_volume);
+    }
+
+    fn start_music(&mut self, volume: f32) {
+        self.play(MUSIC, volume);
+        self.is_music_playing = true;
+        self.music_volume = Some(volume);
+    }
+
+    fn stop_music(&mut self) {
+        if self.loaded.contains(MUSIC) {
+            stop_sound(MUSIC);
+        }
+        self.is_music_playing = false;
+        self.music_volume = None;
+    }
+
+    /// Fades the playing music to the given volume, small changes are skipped
+    /// to avoid flooding the audio thread with commands every frame
+    fn set_music_volume(&mut self, volume: f32) {
+        if self
+            .music_volume
+            .is_some_and(|current| (current - volume).abs() < 0.01)
+        {
+            return;
+        }
+        let mut assets = ASSETS.borrow_mut();
+        // The handle only exists once comfy processed the play queue
+        let Some(handle) = assets.sound_handles.get_mut(&sound_id(MUSIC)) else {
+            return;
+        };
+        if handle
+            .set_volume(Volume::Amplitude(volume as f64), Tween::default())
+            .is_ok()
+        {
+            self.music_volume = Some(volume);
+        }
+    }
+}
+
+impl Audio {
+    pub fn on_event(&mut self, _time_secs: f32, event: &GameEvent) {
+        match event {
+            // Traps trigger on a fixed clock, only hits are worth hearing
+            GameEvent::TrapTriggered {
+                source,
+                targets_hit,
+            } if *targets_hit > 0 => {
+                self.play_effect(source.trap_kind.sprite_name());
+            }
+            GameEvent::EnemyKilled { .. } => self.play_effect(ENEMY_KILLED),
+            GameEvent::PlayerDamaged { .. } => self.play_effect(PLAYER_DAMAGED),
+            GameEvent::GoldChanged {
+                reason: GoldChangeReason::Interest,
+                ..
+            } => self.play_effect(INTEREST),
+            GameEvent::GameOver => {
+                self.stop_music();
+                self.play_effect(GAME_OVER);
+            }
+            GameEvent::Victory => self.stop_music(),
+            _ => {}
+        }
+    }
+}
+
+impl GameState {
+    /// Scales the music with the number of enemies and warns about low HP
+    pub fn update_audio(&mut self) {
+        if self.is_game_over {
+            return;
+        }
+        let intensity = MINIMUM_INTENSITY
+            + (1. - MINIMUM_INTENSITY)
+                * (self.enemies.len() as f32 / MAXIMUM_INTENSITY_ENEMIES).min(1.);
+        let music_volume = self.audio.settings.music_volume * intensity;
+        match self.audio.is_music_playing {
+            true => self.audio.set_music_volume(music_volume),
+            false => self.audio.start_music(music_volume),
+        }
+
+        let is_low_hp = self.player.hp.remaining_fraction() < LOW_HP_FRACTION;
+        if is_low_hp && !self.audio.is_low_hp_warned {
+            self.audio.play_effect(LOW_HP);
+        }
+        // Warns again once the player recovered
+        self.audio.is_low_hp_warned = is_low_hp;
+    }
+
+    pub fn audio_debug_ui(&mut self, ui: &mut egui::Ui) {
+        let before = self.audio.settings;
+        let settings = &mut self.audio.settings;
+        ui.horizontal(|ui| {
+            ui.label("Master volume:");
+            ui.add(egui::Slider::new(&mut settings.master_volume, 0. ..=1.));
+            ui.label("Effects:");
+            ui.add(egui::Slider::new(&mut settings.effects_volume, 0. ..=1.));
+            ui.label("Music:");
+            ui.add(egui::Slider::new(&mut settings.music_volume, 0. ..=1.));
+        });
+        if *settings == before {
+            return;
+        }
+        if settings.master_volume != before.master_volume {
+            set_master_volume(settings.master_volume as f64);
+        }
+        if let Err(err) = settings.save() {
+            self.toasts
+                .error(format!("Failed to save audio settings: {}", err));
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use comfy::*;
+
+use crate::*;
+
+/// Placing traps with the mouse or keyboard, active until cancelled
+#[derive(Clone, Copy)]
+pub struct BuildMode {
+    pub trap_kind: TrapKind,
+    /// Start of a shift-drag, traps are placed along the line once released
+    drag_start: Option<TileMapPos>,
+}
+
+impl BuildMode {
+    pub fn new(trap_kind: TrapKind) -> Self {
+        Self {
+            trap_kind,
+            drag_start: None,
+        }
+    }
+}
+
+/// Tiles from `start` towards `end` along the axis with the larger distance,
+/// so that dragging always places a straight row or column
+pub fn tiles_in_line(start: TileMapPos, end: TileMapPos) -> Vec<TileMapPos> {
+    let columns = start.x.min(end.x)..=start.x.max(end.x);
+    let rows = start.y.min(end.y)..=start.y.max(end.y);
+    let mut tiles = match start.x.abs_diff(end.x) >= start.y.abs_diff(end.y) {
+        true => columns.map(|x| TileMapPos::new(x, start.y)).collect_vec(),
+        false => rows.map(|y| TileMapPos::new(start.x, y)).collect_vec(),
+    };
+    // Built from the start, so running out of gold keeps the traps closest to it
+    if tiles.first() != Some(&start) {
+        tiles.reverse();
+    }
+    tiles
+}
+
+impl GameState {
+    pub fn toggle_build_mode(&mut self, trap_kind: TrapKind) {
+        self.build_mode = match self.build_mode {
+            Some(build_mode) if build_mode.trap_kind == trap_kind => None,
+            _ => Some(BuildMode::new(trap_kind)),
+        };
+    }
+
+    /// Returns whether the inputs were used up by the build mode
+    pub fn build_mode_inputs(&mut self) -> bool {
+        for trap_kind in TrapKind::ALL {
+            if self.keybindings.is_pressed(Action::BuildTrap(trap_kind)) {
+                self.toggle_build_mode(trap_kind);
+            }
+        }
+        let Some(mut build_mode) = self.build_mode else {
+            return false;
+        };
+        if is_mouse_button_pressed(MouseButton::Right)
+            || self.keybindings.is_pressed(Action::Cancel)
+        {
+            self.build_mode = None;
+            return true;
+        }
+
+        let hovered = self.tile_map.pos_from_absolute(mouse_world());
+        let trap_builder = TrapBuilder::default_for(build_mode.trap_kind);
+        // Keyboard-only building on the tile moved to with the selection keys
+        if self.keybindings.is_pressed(Action::Confirm) {
+            if let Some(selected_tile) = self.selected_tile {
+                self.build_trap(selected_tile, trap_builder);
+            }
+        }
+        let is_shift_down = is_key_down(KeyCode::LShift) || is_key_down(KeyCode::RShift);
+        if is_mouse_button_pressed(MouseButton::Left) && !egui().wants_pointer_input() {
+            match (hovered, is_shift_down) {
+                (Some(tile_map_pos), true) => build_mode.drag_start = Some(tile_map_pos),
+                (Some(tile_map_pos), false) => {
+                    self.build_trap(tile_map_pos, trap_builder);
+                }
+                (None, _) => {}
+            }
+        }
+        if is_mouse_button_released(MouseButton::Left) {
+            if let (Some(start), Some(end)) = (build_mode.drag_start.take(), hovered) {
+                for tile_map_pos in tiles_in_line(start, end) {
+                    if self.player.gold.value < trap_builder.gold_cost() {
+                        break;
+                    }
+                    self.build_trap(tile_map_pos, trap_builder);
+                }
+            }
+        }
+        self.build_mode = Some(build_mode);
+        true
+    }
+
+    /// Ghost of the trap under the cursor, or along the dragged line,
+    /// and on the selected tile for building with the keyboard
+    pub fn draw_build_preview(&self) {
+        let Some(build_mode) = self.build_mode else {
+            return;
+        };
+        let trap_builder = TrapBuilder::default_for(build_mode.trap_kind);
+        if let Some(selected_tile) = self.selected_tile {
+            let tint = match self.can_build_on(selected_tile)
+                && self.player.gold.value >= trap_builder.gold_cost()
+            {
+                true => WHITE.alpha(0.6),
+                false => RED.alpha(0.6),
+            };
+            draw_trap_ghost(build_mode.trap_kind, selected_tile, tint);
+        }
+        let Some(hovered) = self.tile_map.pos_from_absolute(mouse_world()) else {
+            return;
+        };
+        let tiles = match build_mode.drag_start {
+            Some(start) => tiles_in_line(start, hovered),
+            None => vec![hovered],
+        };
+        let mut gold_left = self.player.gold.value;
+        for tile_map_pos in tiles {
+            let can_build = self.can_build_on(tile_map_pos);
+            let can_afford = gold_left >= trap_builder.gold_cost();
+            if can_build && can_afford {
+                gold_left -= trap_builder.gold_cost();
+            }
+            let tint = match can_build && can_afford {
+                true => WHITE.alpha(0.6),
+                false => RED.alpha(0.6),
+            };
+            draw_trap_ghost(build_mode.trap_kind, tile_map_pos, tint);
+        }
+        self.draw_area_of_effect(&trap_builder, hovered);
+    }
+
+    fn draw_area_of_effect(&self, trap_builder: &TrapBuilder, tile_map_pos: TileMapPos) {
+        let color = trap_builder.kind().color().alpha(0.8);
+        match trap_builder {
+            TrapBuilder::Slow { area, .. } | TrapBuilder::AntiAir { area, .. } => {
+                for tile in self.tile_map.area_til_distance(tile_map_pos, *area) {
+                    draw_rect_outline(
+                        tile.into_absolute_mid(),
+                        Vec2::splat(tile_map::TILE_SIZE),
+                        1. / 16.,
+                        color,
+                        4,
+                    );
+                }
+            }
+            TrapBuilder::Shooter { range, .. } => {
+                draw_circle_outline(tile_map_pos.into_absolute_mid(), *range, 1. / 16., color, 4)
+            }
+            TrapBuilder::Simple { .. }
+            | TrapBuilder::DamageOverTime { .. }
+            | TrapBuilder::Wall { .. } => draw_rect_outline(
+                tile_map_pos.into_absolute_mid(),
+                Vec2::splat(tile_map::TILE_SIZE),
+                1. / 16.,
+                color,
+                4,
+            ),
+        }
+    }
+}
+
+fn draw_trap_ghost(trap_kind: TrapKind, tile_map_pos: TileMapPos, tint: Color) {
+    let size = Vec2::splat(0.9 * tile_map::TILE_SIZE);
+    let position = tile_map_pos.into_absolute_mid();
+    if !draw_sprite_frame(trap_kind.sprite_name(), position, size, tint, 3, 0) {
+        draw_rect(
+            position,
+            size,
+            trap_kind.color().mix(tint, 0.5).alpha(0.6),
+            3,
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use comfy::*;
+
+use crate::{tile_map::MARGIN, *};
+
+const MIN_ZOOM: f32 = 1.;
+const MAX_ZOOM: f32 = 8.;
+/// Zoom factor per point scrolled with the mouse wheel
+const SCROLL_ZOOM_SPEED: f32 = 0.002;
+/// Zoom factor per press of the zoom keys
+const KEY_ZOOM_STEP: f32 = 1.25;
+/// The map keeps at least this fraction of the screen, even when the UI covers more
+const MIN_FREE_FRACTION: f32 = 0.25;
+
+/// Shows the map in the part of the screen not covered by the UI, zoomed and
+/// panned by the player. Kept across runs.
+pub struct MapCamera {
+    /// Magnification of the fitted map, 1 shows all of it
+    pub zoom: f32,
+    /// Offset of the view from the middle of the map, in world coordinates
+    pub pan: Vec2,
+    /// Screen area left free by the egui panels in the last frame, in egui points
+    free_rect: Option<egui::Rect>,
+}
+
+impl Default for MapCamera {
+    fn default() -> Self {
+        Self {
+            zoom: 1.,
+            pan: Vec2::ZERO,
+            free_rect: None,
+        }
+    }
+}
+
+/// Screen area in physical pixels which the map is fitted to, as its minimum and size
+fn free_screen_area(free_rect: Option<egui::Rect>) -> (Vec2, Vec2) {
+    let screen = Vec2::new(screen_width(), screen_height());
+    let Some(free_rect) = free_rect else {
+        return (Vec2::ZERO, screen);
+    };
+    let pixels_per_point = egui().pixels_per_point();
+    let min = Vec2::new(free_rect.min.x, free_rect.min.y) * pixels_per_point;
+    let size = Vec2::new(free_rect.width(), free_rect.height()) * pixels_per_point;
+    let size = size.max(screen * MIN_FREE_FRACTION);
+    (min.min(screen - size), size)
+}
+
+impl GameState {
+    /// Fits the map with its margin into the free part of the screen, whatever
+    /// the window size and aspect ratio, then applies the zoom and pan
+    pub fn fit_camera(&self) {
+        let screen = Vec2::new(screen_width(), screen_height());
+        if screen.min_element() <= 0. {
+            return;
+        }
+        let (free_min, free_size) = free_screen_area(self.camera.free_rect);
+        let map_size = Vec2::new(self.tile_map.width(), self.tile_map.height());
+        let world_per_pixel =
+            ((map_size + Vec2::splat(2. * MARGIN)) / free_size).max_element() / self.camera.zoom;
+
+        let mut camera = main_camera_mut();
+        camera.zoom = world_per_pixel * screen.x;
+        camera.desired_zoom = camera.zoom;
+        // Screen coordinates grow downwards, the world ones upwards
+        let free_offset = (free_min + free_size / 2. - screen / 2.) * world_per_pixel;
+        camera.center = map_size / 2. - Vec2::new(free_offset.x, -free_offset.y) + self.camera.pan;
+    }
+
+    /// Zooms by `factor` around a world position, which stays in place on the screen
+    fn zoom_camera(&mut self, factor: f32, around: Vec2) {
+        let zoom = (self.camera.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let factor = zoom / self.camera.zoom;
+        let center = main_camera().center;
+        self.camera.pan += (around - center) * (1. - 1. / factor);
+        self.camera.zoom = zoom;
+        self.clamp_camera_pan();
+    }
+
+    /// Keeps at least the middle of the map on the screen
+    fn clamp_camera_pan(&mut self) {
+        let half_map = Vec2::new(self.tile_map.width(), self.tile_map.height()) / 2.;
+        self.camera.pan = self.camera.pan.clamp(-half_map, half_map);
+    }
+
+    /// Mouse wheel or pinch to zoom, middle mouse button or two fingers to pan
+    pub fn camera_inputs(&mut self) {
+        let pixels_per_point = egui().pixels_per_point();
+        let (scroll_delta, zoom_delta, hover_pos, is_middle_down, pointer_delta, multi_touch) =
+            egui().input(|input| {
+                (
+                    input.scroll_delta,
+                    input.zoom_delta(),
+                    input.pointer.hover_pos(),
+                    input.pointer.middle_down(),
+                    input.pointer.delta(),
+                    input.multi_touch(),
+                )
+            });
+        let world_per_point = main_camera().zoom / screen_width() * pixels_per_point;
+
+        if !egui().wants_pointer_input() {
+            // Pinching reports the first finger as the pointer, close enough to zoom around
+            let factor = zoom_delta * (scroll_delta.y * SCROLL_ZOOM_SPEED).exp();
+            if factor != 1. {
+                if let Some(around) = hover_pos {
+                    let around = Vec2::new(around.x, around.y) * pixels_per_point;
+                    self.zoom_camera(factor, screen_to_world(around));
+                }
+            }
+            let dragged = match (multi_touch, is_middle_down) {
+                (Some(multi_touch), _) => Some(multi_touch.translation_delta),
+                (None, true) => Some(pointer_delta),
+                (None, false) => None,
+            };
+            if let Some(dragged) = dragged {
+                self.camera.pan -= Vec2::new(dragged.x, -dragged.y) * world_per_point;
+                self.clamp_camera_pan();
+            }
+        }
+
+        let center = main_camera().center;
+        if self.keybindings.is_pressed(Action::ZoomIn) {
+            self.zoom_camera(KEY_ZOOM_STEP, center);
+        }
+        if self.keybindings.is_pressed(Action::ZoomOut) {
+            self.zoom_camera(1. / KEY_ZOOM_STEP, center);
+        }
+        if self.keybindings.is_pressed(Action::ResetCamera) {
+            self.camera.zoom = 1.;
+            self.camera.pan = Vec2::ZERO;
+        }
+    }
+
+    /// Pans just enough for the tile to be visible outside of the UI
+    pub fn scroll_camera_to(&mut self, tile_map_pos: TileMapPos) {
+        let (free_min, free_size) = free_screen_area(self.camera.free_rect);
+        // Screen coordinates grow downwards, so the bottom of the free area is lower in the world
+        let bottom_left = screen_to_world(Vec2::new(free_min.x, free_min.y + free_size.y));
+        let top_right = screen_to_world(Vec2::new(free_min.x + free_size.x, free_min.y));
+        let tile_start = tile_map_pos.into_absolute_start();
+        let tile_end = tile_map_pos.into_absolute_end();
+        self.camera.pan += (tile_start - bottom_left).min(Vec2::ZERO);
+        self.camera.pan += (tile_end - top_right).max(Vec2::ZERO);
+        self.clamp_camera_pan();
+    }
+
+    /// Remembers which part of the screen the UI panels left for the map
+    pub fn update_camera_free_rect(&mut self) {
+        self.camera.free_rect = Some(egui().available_rect());
+    }
+}
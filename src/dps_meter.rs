@@ -0,0 +1,62 @@
+use comfy::*;
+
+use crate::*;
+
+impl GameState {
+    pub fn trap_dps_ui(&self, ui: &mut egui::Ui, source: DamageSource) {
+        let trap_statistics = self
+            .statistics
+            .per_trap
+            .get(&source)
+            .cloned()
+            .unwrap_or_default();
+        egui::Grid::new("Trap DPS").show(ui, |ui| {
+            ui.label(format!("DPS (last {:.0}s):", DPS_WINDOW_SECS));
+            ui.label(format!(
+                "{:.2}",
+                trap_statistics.recent_dps(self.run_length_seconds)
+            ));
+            ui.end_row();
+            ui.label("Total damage:");
+            ui.label(format!("{:.0}", trap_statistics.damage_dealt));
+            ui.end_row();
+            ui.label("Kills:");
+            ui.label(trap_statistics.kills.to_string());
+            ui.end_row();
+            ui.label("Damage per gold:");
+            ui.label(
+                trap_statistics
+                    .damage_per_gold()
+                    .map_or("-".to_string(), |damage_per_gold| {
+                        format!("{:.2}", damage_per_gold)
+                    }),
+            );
+            ui.end_row();
+        });
+    }
+
+    /// Live damage per second written over every built trap
+    pub fn draw_dps_overlay(&self) {
+        if !self.show_dps_overlay {
+            return;
+        }
+        for (tile_map_pos, trap_tile) in self.trap_tiles.iter() {
+            let TrapTile::Built { trap, .. } = trap_tile else {
+                continue;
+            };
+            let source = DamageSource {
+                tile: *tile_map_pos,
+                trap_kind: trap.kind(),
+            };
+            let Some(trap_statistics) = self.statistics.per_trap.get(&source) else {
+                continue;
+            };
+            draw_text(
+                &format!("{:.1}", trap_statistics.recent_dps(self.run_length_seconds)),
+                tile_map_pos.into_absolute_mid(),
+                BLACK,
+                TextAlign::Center,
+            );
+        }
+    }
+}
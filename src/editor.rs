@@ -0,0 +1,312 @@
+use comfy::{egui::Align2, *};
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Brush {
+    Ground,
+    Road,
+    Blocked,
+    Spawn,
+    Exit,
+    Terrain(Terrain),
+}
+
+impl Brush {
+    pub fn all() -> Vec<Brush> {
+        let mut result = vec![
+            Brush::Ground,
+            Brush::Road,
+            Brush::Blocked,
+            Brush::Spawn,
+            Brush::Exit,
+            Brush::Terrain(Terrain::Mud),
+            Brush::Terrain(Terrain::Ice),
+            Brush::Terrain(Terrain::Rock),
+            Brush::Terrain(Terrain::Fertile),
+        ];
+        result.extend(
+            TrapKind::ALL
+                .iter()
+                .map(|trap_kind| Brush::Terrain(Terrain::Amplifier(*trap_kind))),
+        );
+        result
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Brush::Ground => "Ground".to_string(),
+            Brush::Road => "Road".to_string(),
+            Brush::Blocked => "Blocked".to_string(),
+            Brush::Spawn => "Spawn".to_string(),
+            Brush::Exit => "Exit".to_string(),
+            Brush::Terrain(terrain) => terrain.name(),
+        }
+    }
+}
+
+pub struct MapEditor {
+    pub brush: Brush,
+    /// Path of the map file on native, its name in the browser's storage on the web
+    pub file_path: String,
+    pub error: Option<String>,
+}
+
+impl Default for MapEditor {
+    fn default() -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let file_path = "maps/custom.json".to_string();
+        #[cfg(target_arch = "wasm32")]
+        let file_path = "custom".to_string();
+        Self {
+            brush: Brush::Blocked,
+            file_path,
+            error: None,
+        }
+    }
+}
+
+/// Browsers cannot write files, edited maps are kept in the storage instead
+#[cfg(target_arch = "wasm32")]
+fn map_storage_key(name: &str) -> String {
+    format!("map.{}", name)
+}
+
+fn save_edited_map(tile_map: &TileMap, file_path: &str) -> Result<()> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return tile_map.save(Path::new(file_path));
+    #[cfg(target_arch = "wasm32")]
+    storage()?.write(&map_storage_key(file_path), &tile_map.to_json()?)
+}
+
+fn load_edited_map(file_path: &str) -> Result<TileMap> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return TileMap::load(Path::new(file_path));
+    #[cfg(target_arch = "wasm32")]
+    match storage()?.read(&map_storage_key(file_path))? {
+        Some(json) => TileMap::parse(&json),
+        None => bail!("There is no saved map named {}", file_path),
+    }
+}
+
+impl TileMap {
+    pub fn paint(&mut self, tile_map_pos: TileMapPos, brush: Brush) {
+        self.blocked.remove(&tile_map_pos);
+        self.unbuildable.remove(&tile_map_pos);
+        self.spawns.retain(|spawn| *spawn != tile_map_pos);
+        self.exits.remove(&tile_map_pos);
+        self.terrain.remove(&tile_map_pos);
+        match brush {
+            Brush::Ground => {}
+            Brush::Road => {
+                self.unbuildable.insert(tile_map_pos);
+            }
+            Brush::Blocked => {
+                self.blocked.insert(tile_map_pos);
+            }
+            Brush::Spawn => self.spawns.push(tile_map_pos),
+            Brush::Exit => {
+                self.exits.insert(tile_map_pos);
+            }
+            Brush::Terrain(terrain) => {
+                self.terrain.insert(tile_map_pos, terrain);
+            }
+        }
+    }
+
+    pub fn resize(&mut self, columns: u32, rows: u32) {
+        self.columns = columns;
+        self.rows = rows;
+        let in_bounds =
+            |tile_map_pos: &TileMapPos| tile_map_pos.x < columns && tile_map_pos.y < rows;
+        self.blocked.retain(in_bounds);
+        self.unbuildable.retain(in_bounds);
+        self.spawns.retain(in_bounds);
+        self.exits.retain(in_bounds);
+        self.terrain
+            .retain(|tile_map_pos, _| in_bounds(tile_map_pos));
+    }
+}
+
+impl GameState {
+    /// Starts editing the current map, which resets the run
+    pub fn open_editor(&mut self) {
+        let tile_map = std::mem::take(&mut self.tile_map);
+        self.restart_with_map(tile_map);
+        self.editor = Some(MapEditor::default());
+    }
+
+    pub fn editor_inputs(&mut self) {
+        let Some(editor) = self.editor.as_ref() else {
+            return;
+        };
+        // The simulation does not run while editing
+        self.is_paused = true;
+        if is_mouse_button_down(MouseButton::Left) && !egui().wants_pointer_input() {
+            if let Some(tile_map_pos) = self.tile_map.bounded_pos_from_absolute(mouse_world()) {
+                self.tile_map.paint(tile_map_pos, editor.brush);
+                self.distance_fields.invalidate();
+            }
+        }
+    }
+
+    pub fn draw_editor(&self) {
+        if self.editor.is_none() {
+            return;
+        }
+        for tile_map_pos in self.tile_map.blocked.iter() {
+            draw_rect(
+                tile_map_pos.into_absolute_mid(),
+                Vec2::splat(tile_map::TILE_SIZE),
+                BLACK.alpha(0.3),
+                -1,
+            );
+        }
+        draw_rect_outline(
+            Vec2::new(self.tile_map.width(), self.tile_map.height()) / 2.,
+            Vec2::new(self.tile_map.width(), self.tile_map.height()),
+            1. / 32.,
+            BLACK,
+            1,
+        );
+    }
+
+    pub fn editor_ui(&mut self) {
+        let Some(editor) = self.editor.as_mut() else {
+            return;
+        };
+        let mut play = false;
+        egui::Window::new("Map editor")
+            .anchor(Align2::RIGHT_TOP, [0., 0.])
+            .collapsible(false)
+            .show(egui(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut self.tile_map.name);
+                });
+                let mut columns = self.tile_map.columns;
+                let mut rows = self.tile_map.rows;
+                ui.horizontal(|ui| {
+                    ui.label("Columns:");
+                    ui.add(egui::DragValue::new(&mut columns).clamp_range(1..=100));
+                    ui.label("Rows:");
+                    ui.add(egui::DragValue::new(&mut rows).clamp_range(1..=100));
+                });
+                if columns != self.tile_map.columns || rows != self.tile_map.rows {
+                    self.tile_map.resize(columns, rows);
+                    self.distance_fields.invalidate();
+                }
+
+                ui.separator();
+                ui.label("Brush:");
+                ui.horizontal_wrapped(|ui| {
+                    for brush in Brush::all() {
+                        ui.selectable_value(&mut editor.brush, brush, brush.name());
+                    }
+                });
+
+                ui.separator();
+                ui.label("Waves (without any, enemies spawn randomly):");
+                waves_ui(ui, &mut self.tile_map.waves);
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut editor.file_path);
+                    if ui.button("Save").clicked() {
+                        editor.error = save_edited_map(&self.tile_map, &editor.file_path)
+                            .err()
+                            .map(|err| err.to_string());
+                    }
+                    if ui.button("Load").clicked() {
+                        match load_edited_map(&editor.file_path) {
+                            Ok(tile_map) => {
+                                self.tile_map = tile_map;
+                                self.distance_fields.invalidate();
+                                editor.error = None;
+                            }
+                            Err(err) => editor.error = Some(err.to_string()),
+                        }
+                    }
+                    // To share the map or play it on native with `--map`
+                    #[cfg(target_arch = "wasm32")]
+                    if ui.button("Download").clicked() {
+                        let file_name = format!("{}.json", editor.file_path);
+                        editor.error = self
+                            .tile_map
+                            .to_json()
+                            .and_then(|json| download(&file_name, &json))
+                            .err()
+                            .map(|err| err.to_string());
+                    }
+                });
+                if let Some(error) = editor.error.as_ref() {
+                    ui.colored_label(RED, error);
+                }
+                if ui.button("Play this map").clicked() {
+                    play = true;
+                }
+            });
+
+        if play {
+            // Round trip through the definition to validate the map
+            match TileMap::from_definition(&self.tile_map.to_definition()) {
+                Ok(tile_map) => self.restart_with_map(tile_map),
+                Err(err) => editor.error = Some(err.to_string()),
+            }
+        }
+    }
+}
+
+fn waves_ui(ui: &mut egui::Ui, waves: &mut Vec<Wave>) {
+    let mut removed = None;
+    egui::Grid::new("Waves").striped(true).show(ui, |ui| {
+        ui.label("Start (s)");
+        ui.label("Enemy");
+        ui.label("Count");
+        ui.label("Interval (s)");
+        ui.label("HP multiplier");
+        ui.end_row();
+        for (index, wave) in waves.iter_mut().enumerate() {
+            ui.add(
+                egui::DragValue::new(&mut wave.start_secs)
+                    .speed(1.0)
+                    .clamp_range(0. ..=3600.),
+            );
+            egui::ComboBox::from_id_source(("Wave enemy", index))
+                .selected_text(wave.archetype.name())
+                .show_ui(ui, |ui| {
+                    for archetype in EnemyArchetype::ALL {
+                        ui.selectable_value(&mut wave.archetype, archetype, archetype.name());
+                    }
+                });
+            ui.add(egui::DragValue::new(&mut wave.count).clamp_range(1..=1000));
+            ui.add(
+                egui::DragValue::new(&mut wave.interval_secs)
+                    .speed(0.1)
+                    .clamp_range(0.1..=60.),
+            );
+            ui.add(
+                egui::DragValue::new(&mut wave.hp_multiplier)
+                    .speed(0.1)
+                    .clamp_range(0.1..=100.),
+            );
+            if ui.button("Remove").clicked() {
+                removed = Some(index);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(index) = removed {
+        waves.remove(index);
+    }
+    if ui.button("Add wave").clicked() {
+        // New waves start after the last one has finished spawning
+        let start_secs = waves.last().map_or(0., |wave| {
+            wave.start_secs + wave.interval_secs * wave.count as f32
+        });
+        waves.push(Wave {
+            start_secs,
+            ..Wave::default()
+        });
+    }
+}
@@ -0,0 +1,124 @@
+use comfy::*;
+
+use crate::*;
+
+const PULSE_SECS: f32 = 0.3;
+const RING_SECS: f32 = 0.5;
+const BEAM_SECS: f32 = 0.15;
+
+pub enum EffectKind {
+    /// Tile flashing and fading out
+    Pulse {
+        tile_map_pos: TileMapPos,
+        color: Color,
+    },
+    /// Ring growing from the center up to the radius
+    Ring {
+        center: Vec2,
+        radius: f32,
+        color: Color,
+    },
+    /// Line from a trap to what it shot at
+    Beam { from: Vec2, to: Vec2, color: Color },
+}
+
+pub struct Effect {
+    pub kind: EffectKind,
+    pub age_secs: f32,
+    pub duration_secs: f32,
+}
+
+impl Effect {
+    fn draw(&self) {
+        let progress = (self.age_secs / self.duration_secs).clamp(0., 1.);
+        let fade = 1. - quad_in(progress);
+        match self.kind {
+            EffectKind::Pulse {
+                tile_map_pos,
+                color,
+            } => draw_rect(
+                tile_map_pos.into_absolute_mid(),
+                Vec2::splat(tile_map::TILE_SIZE * (0.6 + 0.4 * cubic_out(progress))),
+                color.alpha(0.8 * fade),
+                2,
+            ),
+            EffectKind::Ring {
+                center,
+                radius,
+                color,
+            } => draw_circle_outline(
+                center,
+                radius * cubic_out(progress),
+                0.08,
+                color.alpha(fade),
+                2,
+            ),
+            EffectKind::Beam { from, to, color } => {
+                draw_line(from, to, 0.1 * fade, color.alpha(fade), 2)
+            }
+        }
+    }
+}
+
+/// Short-lived visuals which outlast the frame they were started in
+#[derive(Default)]
+pub struct Effects {
+    effects: Vec<Effect>,
+}
+
+impl Effects {
+    pub fn spawn(&mut self, kind: EffectKind, duration_secs: f32) {
+        self.effects.push(Effect {
+            kind,
+            age_secs: 0.,
+            duration_secs,
+        });
+    }
+
+    pub fn pulse(&mut self, tile_map_pos: TileMapPos, color: Color) {
+        self.spawn(
+            EffectKind::Pulse {
+                tile_map_pos,
+                color,
+            },
+            PULSE_SECS,
+        );
+    }
+
+    pub fn ring(&mut self, center: Vec2, radius: f32, color: Color) {
+        self.spawn(
+            EffectKind::Ring {
+                center,
+                radius,
+                color,
+            },
+            RING_SECS,
+        );
+    }
+
+    pub fn beam(&mut self, from: Vec2, to: Vec2, color: Color) {
+        self.spawn(EffectKind::Beam { from, to, color }, BEAM_SECS);
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.effects.retain_mut(|effect| {
+            effect.age_secs += delta;
+            effect.age_secs < effect.duration_secs
+        });
+    }
+
+    pub fn draw(&self) {
+        for effect in self.effects.iter() {
+            effect.draw();
+        }
+    }
+}
+
+impl GameState {
+    pub fn update_effects(&mut self) {
+        if self.is_paused {
+            return;
+        }
+        self.effects.update(self.delta);
+    }
+}
@@ -1,49 +1,175 @@
 use comfy::*;
+use serde::{Deserialize, Serialize};
 
 use crate::*;
 
+#[derive(Serialize, Deserialize)]
 pub struct Enemy {
+    pub archetype: EnemyArchetype,
     pub hp: HitPoints,
     pub speed: f32,
     pub damage: f32,
+    #[serde(with = "vec2_serde")]
     pub position: Vec2,
+    /// Tile the enemy is currently walking towards
+    pub next_tile: TileMapPos,
     pub size: f32,
     pub damage_over_time_effects: Vec<DamageOverTimeEffect>,
     pub gold_for_kill: f32,
     pub slow_effects: Vec<SlowEffect>,
+    /// Trap credited with the kill
+    pub last_hit_by: Option<DamageSource>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EnemyArchetype {
+    Walker,
+    Dodger,
+    Seeker,
+    Flyer,
+}
+
+impl EnemyArchetype {
+    pub const ALL: [EnemyArchetype; 4] = [
+        EnemyArchetype::Walker,
+        EnemyArchetype::Dodger,
+        EnemyArchetype::Seeker,
+        EnemyArchetype::Flyer,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnemyArchetype::Walker => "Walker",
+            EnemyArchetype::Dodger => "Dodger",
+            EnemyArchetype::Seeker => "Seeker",
+            EnemyArchetype::Flyer => "Flyer",
+        }
+    }
+
+    pub fn movement(&self) -> EnemyMovement {
+        match self {
+            EnemyArchetype::Walker => EnemyMovement::Straight,
+            EnemyArchetype::Dodger => EnemyMovement::AvoidDamage,
+            EnemyArchetype::Seeker => EnemyMovement::ShortestPath,
+            EnemyArchetype::Flyer => EnemyMovement::Straight,
+        }
+    }
+
+    pub fn layer(&self) -> MovementLayer {
+        match self {
+            EnemyArchetype::Flyer => MovementLayer::Air,
+            _ => MovementLayer::Ground,
+        }
+    }
+
+    pub fn sprite_name(&self) -> &'static str {
+        match self {
+            EnemyArchetype::Walker => "enemies/walker",
+            EnemyArchetype::Dodger => "enemies/dodger",
+            EnemyArchetype::Seeker => "enemies/seeker",
+            EnemyArchetype::Flyer => "enemies/flyer",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            EnemyArchetype::Walker => RED,
+            EnemyArchetype::Dodger => MAGENTA,
+            EnemyArchetype::Seeker => BROWN,
+            EnemyArchetype::Flyer => VIOLET,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct DamageOverTimeEffect {
+    #[serde(with = "timer_serde")]
     pub timer: Timer,
     pub damage_per_second: f32,
+    pub source: DamageSource,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct SlowEffect {
+    #[serde(with = "timer_serde")]
     pub timer: Timer,
     pub strength: f32,
 }
 
 impl Enemy {
-    pub fn move_and_deal_damage(&mut self, delta_secs: f32) -> Option<f32> {
+    /// Returns the damage actually taken, not counting overkill
+    pub fn take_damage(&mut self, damage: f32, source: DamageSource) -> f32 {
+        // The killing blow gets the credit, not hits on an already dead enemy
+        if !self.hp.is_dead() {
+            self.last_hit_by = Some(source);
+        }
+        self.hp.take_damage(damage)
+    }
+
+    pub fn move_and_deal_damage(
+        &mut self,
+        delta_secs: f32,
+        distance_field: &DistanceField,
+        tile_map: &TileMap,
+    ) -> Option<f32> {
         self.slow_effects.retain_mut(|effect| {
             effect.timer.tick_secs(delta_secs);
             !effect.timer.just_finished()
         });
         let total_slow_effect: f32 = self.slow_effects.iter().map(|effect| effect.strength).sum();
-        let speed = self.speed / (1. + total_slow_effect);
-        self.position.x += speed * delta_secs;
-        match self.position.x >= tile_map::x_max() {
-            true => Some(self.damage),
-            false => None,
+        let terrain_speed_multiplier = tile_map
+            .pos_from_absolute(self.position)
+            .map_or(1., |tile_map_pos| {
+                terrain_speed_multiplier(tile_map_pos, self.archetype.layer(), tile_map)
+            });
+        let speed = self.speed * terrain_speed_multiplier / (1. + total_slow_effect);
+
+        let mut distance_left = speed * delta_secs;
+        while distance_left > 0. {
+            let to_target = self.next_tile.into_absolute_mid() - self.position;
+            let distance = to_target.length();
+            if distance > distance_left {
+                self.position += to_target / distance * distance_left;
+                return None;
+            }
+            self.position += to_target;
+            distance_left -= distance;
+            match distance_field.next_step(self.next_tile) {
+                Step::Move(tile_map_pos) => self.next_tile = tile_map_pos,
+                Step::Exit => return Some(self.damage),
+            }
         }
+        None
     }
 
     pub fn draw(&self) {
-        // resolves the case at the beginning of the map, when the whole square isn't seen
-        let x_min = tile_map::x_min().max(self.position.x - 0.5 * self.size);
-        let x_max = self.position.x + 0.5 * self.size;
-        let x_mid = (x_min + x_max) * 0.5;
-        let x_size = x_max - x_min;
+        // Slowed enemies are tinted blue, the stronger the slow the bluer
+        let total_slow_effect: f32 = self.slow_effects.iter().map(|effect| effect.strength).sum();
+        let slow_tint = 0.6 * total_slow_effect.min(1.);
+
+        let walk_frame = (get_time() as f32 * WALK_ANIMATION_FPS) as u32;
+        if draw_sprite_frame(
+            self.archetype.sprite_name(),
+            self.position,
+            Vec2::splat(self.size),
+            WHITE.mix(SKYBLUE, slow_tint),
+            10,
+            walk_frame,
+        ) {
+            self.draw_life_bar_above();
+        } else {
+            self.draw_fallback(self.archetype.color().mix(SKYBLUE, slow_tint));
+        }
+
+        if !self.damage_over_time_effects.is_empty() {
+            self.draw_poison_bubbles();
+        }
+    }
+
+    /// Colored square filled from the bottom up by the remaining life
+    fn draw_fallback(&self, color: Color) {
+        let x_mid = self.position.x;
+        let x_size = self.size;
 
         // life-bar
         let y_min = self.position.y - 0.5 * self.size;
@@ -54,7 +180,7 @@ impl Enemy {
         draw_rect(
             Vec2::new(x_mid, self.position.y),
             Vec2::new(x_size, self.size),
-            RED,
+            color,
             10,
         );
         draw_rect(
@@ -65,24 +191,54 @@ impl Enemy {
         );
     }
 
-    pub fn on_tiles(&self) -> HashSet<TileMapPos> {
-        // TODO: This only works if size < TILE_SIZE
-        let min_x = self.position.x - self.size / 2.;
-        let max_x = self.position.x + self.size / 2.;
-
-        // TODO: This only make sense if every enemy is in a specific row
-        let y = tile_map::y_from_absolute(self.position.y);
+    fn draw_life_bar_above(&self) {
+        let height = 0.06;
+        let y_mid = self.position.y + 0.5 * self.size + height;
+        let x_min = self.position.x - 0.5 * self.size;
+        let width = self.size * self.hp.remaining_fraction().max(0.);
+        draw_rect(
+            Vec2::new(self.position.x, y_mid),
+            Vec2::new(self.size, height),
+            DARKGRAY,
+            11,
+        );
+        draw_rect(
+            Vec2::new(x_min + 0.5 * width, y_mid),
+            Vec2::new(width, height),
+            DARKGREEN,
+            12,
+        );
+    }
 
-        let mut result = HashSet::new();
-        if let Some(y) = y {
-            if let Some(x) = tile_map::x_from_absolute(min_x) {
-                result.insert(TileMapPos { x, y });
-            }
-            if let Some(x) = tile_map::x_from_absolute(max_x) {
-                result.insert(TileMapPos { x, y });
-            }
+    /// Bubbles rising from the top of a poisoned enemy, looping every second
+    fn draw_poison_bubbles(&self) {
+        for index in 0..3 {
+            let phase = (get_time() as f32 + index as f32 / 3.).fract();
+            let offset = Vec2::new(
+                (index as f32 - 1.) * self.size / 3.,
+                self.size / 2. + phase * 0.3,
+            );
+            draw_circle(
+                self.position + offset,
+                0.03 + 0.04 * (1. - phase),
+                PURPLE.alpha(1. - phase),
+                12,
+            );
         }
-        result
+    }
+
+    pub fn on_tiles(&self, tile_map: &TileMap) -> HashSet<TileMapPos> {
+        // TODO: This only works if size < TILE_SIZE
+        let half_size = self.size / 2.;
+        [
+            Vec2::new(-half_size, -half_size),
+            Vec2::new(-half_size, half_size),
+            Vec2::new(half_size, -half_size),
+            Vec2::new(half_size, half_size),
+        ]
+        .into_iter()
+        .filter_map(|corner| tile_map.pos_from_absolute(self.position + corner))
+        .collect()
     }
 }
 
@@ -94,7 +250,17 @@ impl GameState {
         self.enemies.retain(|enemy| {
             let is_dead = enemy.hp.is_dead();
             if is_dead {
-                self.player.gold.value += enemy.gold_for_kill
+                self.player.gold.value += enemy.gold_for_kill;
+                self.event_bus.emit(GameEvent::EnemyKilled {
+                    archetype: enemy.archetype,
+                    by: enemy.last_hit_by,
+                    gold: enemy.gold_for_kill,
+                    position: enemy.position,
+                });
+                self.event_bus.emit(GameEvent::GoldChanged {
+                    amount: enemy.gold_for_kill,
+                    reason: GoldChangeReason::Kill,
+                });
             }
             !is_dead
         })
@@ -110,34 +276,64 @@ impl GameState {
         if self.is_paused {
             return;
         }
-        self.enemies
-            .retain_mut(|enemy| match enemy.move_and_deal_damage(self.delta) {
+        let distance_fields = self.distance_fields.get(&self.tile_map, &self.trap_tiles);
+        self.enemies.retain_mut(|enemy| {
+            let distance_field =
+                &distance_fields[&(enemy.archetype.movement(), enemy.archetype.layer())];
+            match enemy.move_and_deal_damage(self.delta, distance_field, &self.tile_map) {
                 None => true,
                 Some(damage) => {
                     self.player.hp.take_damage(damage);
+                    self.event_bus.emit(GameEvent::PlayerDamaged { damage });
                     false
                 }
-            })
+            }
+        })
     }
 
     pub fn deal_damage_over_time_to_enemies(&mut self) {
         if self.is_paused {
             return;
         }
-        self.enemies.iter_mut().for_each(|enemy| {
-            enemy.damage_over_time_effects.retain_mut(|dot| {
-                let initial_elapsed = dot.timer.elapsed_secs();
-                dot.timer.tick_secs(self.delta);
-
-                // makes sure it does not deal damage over the max time.
-                let current_elapsed = match dot.timer.just_finished() {
-                    true => dot.timer.duration().as_secs_f32(),
-                    false => dot.timer.elapsed_secs(),
-                };
-                let dot_duration = current_elapsed - initial_elapsed;
-                enemy.hp.take_damage(dot.damage_per_second * dot_duration);
-                !dot.timer.just_finished()
+        // One event per trap and step, instead of one per effect on every enemy
+        let mut dealt: Vec<(DamageSource, f32, Vec2)> = Vec::new();
+        self.enemies
+            .iter_mut()
+            .filter(|enemy| !enemy.hp.is_dead())
+            .for_each(|enemy| {
+                enemy.damage_over_time_effects.retain_mut(|dot| {
+                    let initial_elapsed = dot.timer.elapsed_secs();
+                    dot.timer.tick_secs(self.delta);
+
+                    // makes sure it does not deal damage over the max time.
+                    let current_elapsed = match dot.timer.just_finished() {
+                        true => dot.timer.duration().as_secs_f32(),
+                        false => dot.timer.elapsed_secs(),
+                    };
+                    let dot_duration = current_elapsed - initial_elapsed;
+                    // Not through `Enemy::take_damage`, the effects themselves are borrowed
+                    if !enemy.hp.is_dead() {
+                        enemy.last_hit_by = Some(dot.source);
+                    }
+                    let damage = enemy.hp.take_damage(dot.damage_per_second * dot_duration);
+                    match dealt.iter_mut().find(|(source, ..)| *source == dot.source) {
+                        Some((_, total, position)) => {
+                            *total += damage;
+                            *position = enemy.position;
+                        }
+                        None => dealt.push((dot.source, damage, enemy.position)),
+                    }
+                    !dot.timer.just_finished()
+                });
             });
-        })
+        for (source, damage, position) in dealt {
+            if damage > 0. {
+                self.event_bus.emit(GameEvent::DamageDealt {
+                    source,
+                    damage,
+                    position,
+                });
+            }
+        }
     }
 }
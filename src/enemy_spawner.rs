@@ -1,17 +1,59 @@
 use comfy::*;
+use serde::{Deserialize, Serialize};
 
 use crate::*;
 
+#[derive(Serialize, Deserialize)]
 pub struct EnemySpawner {
+    #[serde(with = "timer_serde")]
     timer: Timer,
     maximum_hp: f32,
     speed: f32,
     damage: f32,
     gold_for_kill: f32,
+    archetypes: Vec<EnemyArchetype>,
     progression: EnemySpawnerProgression,
+    /// How many enemies of each wave of the map's script were already spawned
+    spawned_per_wave: Vec<u32>,
 }
 
+/// A scripted group of enemies, maps with waves do not spawn enemies randomly
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Wave {
+    pub start_secs: f32,
+    pub archetype: EnemyArchetype,
+    pub count: u32,
+    pub interval_secs: f32,
+    pub hp_multiplier: f32,
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Self {
+            start_secs: 0.,
+            archetype: EnemyArchetype::Walker,
+            count: 10,
+            interval_secs: 1.,
+            hp_multiplier: 1.,
+        }
+    }
+}
+
+impl Wave {
+    /// Number of enemies which should have been spawned by the given time
+    pub fn due_count(&self, run_length_seconds: f32) -> u32 {
+        if run_length_seconds < self.start_secs {
+            return 0;
+        }
+        let spawned = ((run_length_seconds - self.start_secs) / self.interval_secs) as u32 + 1;
+        spawned.min(self.count)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct EnemySpawnerProgression {
+    #[serde(with = "timer_serde")]
     timer: Timer,
     maximum_hp_increase: f32,
     speed_increase: f32,
@@ -27,6 +69,7 @@ impl Default for EnemySpawner {
             speed: 1.,
             damage: 3.,
             gold_for_kill: 1.,
+            archetypes: EnemyArchetype::ALL.to_vec(),
             progression: EnemySpawnerProgression {
                 timer: Timer::from_seconds(30., true),
                 maximum_hp_increase: 0.3,
@@ -34,6 +77,7 @@ impl Default for EnemySpawner {
                 damage_increase: 0.2,
                 gold_for_kill_increase: 0.1,
             },
+            spawned_per_wave: vec![],
         }
     }
 }
@@ -53,28 +97,102 @@ impl GameState {
                 1. + self.enemy_spawner.progression.maximum_hp_increase;
         }
 
+        if !self.tile_map.waves.is_empty() {
+            self.spawn_scripted_enemies();
+            return;
+        }
+
         self.enemy_spawner.timer.tick_secs(self.delta);
         if self.enemy_spawner.timer.just_finished() {
-            self.enemies.push(self.enemy_spawner.spawn_single())
+            let archetype = self.enemy_spawner.random_archetype(&mut self.rng);
+            self.enemies.push(self.enemy_spawner.spawn_single(
+                archetype,
+                1.,
+                &self.tile_map,
+                &mut self.rng,
+            ));
+            self.event_bus.emit(GameEvent::EnemySpawned { archetype });
+        }
+    }
+
+    fn spawn_scripted_enemies(&mut self) {
+        let waves = &self.tile_map.waves;
+        let spawned_per_wave = &mut self.enemy_spawner.spawned_per_wave;
+        spawned_per_wave.resize(waves.len(), 0);
+
+        let mut to_spawn = vec![];
+        for (index, (wave, spawned)) in waves.iter().zip(spawned_per_wave.iter_mut()).enumerate() {
+            let due = wave.due_count(self.run_length_seconds);
+            if *spawned == 0 && due > 0 {
+                self.event_bus.emit(GameEvent::WaveStarted { index });
+            }
+            for _ in *spawned..due {
+                to_spawn.push((wave.archetype, wave.hp_multiplier));
+            }
+            *spawned = due.max(*spawned);
+        }
+
+        for (archetype, hp_multiplier) in to_spawn {
+            self.enemies.push(self.enemy_spawner.spawn_single(
+                archetype,
+                hp_multiplier,
+                &self.tile_map,
+                &mut self.rng,
+            ));
+            self.event_bus.emit(GameEvent::EnemySpawned { archetype });
         }
     }
 }
 
 impl EnemySpawner {
-    fn spawn_single(&self) -> Enemy {
+    pub fn maximum_hp(&self) -> f32 {
+        self.maximum_hp
+    }
+
+    /// Time until enemies get stronger again
+    pub fn secs_until_progression(&self) -> f32 {
+        let timer = &self.progression.timer;
+        (timer.duration() - timer.elapsed()).as_secs_f32()
+    }
+
+    /// Whether the map's script is finite and spawned all of its enemies
+    pub fn all_waves_spawned(&self, waves: &[Wave]) -> bool {
+        !waves.is_empty()
+            && self.spawned_per_wave.len() == waves.len()
+            && waves
+                .iter()
+                .zip(self.spawned_per_wave.iter())
+                .all(|(wave, spawned)| *spawned >= wave.count)
+    }
+
+    fn random_archetype(&self, rng: &mut RunRng) -> EnemyArchetype {
+        rng.choose(&self.archetypes)
+            .unwrap_or(EnemyArchetype::Walker)
+    }
+
+    fn spawn_single(
+        &self,
+        archetype: EnemyArchetype,
+        hp_multiplier: f32,
+        tile_map: &TileMap,
+        rng: &mut RunRng,
+    ) -> Enemy {
         let size = 0.5;
+        let spawn = rng
+            .choose(&tile_map.spawns)
+            .expect("Maps are validated to have a spawn");
         Enemy {
-            hp: HitPoints::new_full(self.maximum_hp),
+            archetype,
+            hp: HitPoints::new_full(self.maximum_hp * hp_multiplier),
             speed: self.speed,
             damage: self.damage,
-            position: Vec2::new(
-                tile_map::x_min() - size / 2.,
-                tile_map::y_into_absolute_mid(rand() % tile_map::ROWS),
-            ),
+            position: spawn.into_absolute_mid(),
+            next_tile: spawn,
             size,
             damage_over_time_effects: vec![],
             gold_for_kill: self.gold_for_kill,
             slow_effects: vec![],
+            last_hit_by: None,
         }
     }
 
@@ -124,6 +242,18 @@ impl EnemySpawner {
             self.timer
                 .set_duration(Duration::from_secs_f32(spawn_cooldown));
         });
+        ui.horizontal(|ui| {
+            ui.label("Archetypes:");
+            for archetype in EnemyArchetype::ALL {
+                let mut enabled = self.archetypes.contains(&archetype);
+                if ui.checkbox(&mut enabled, archetype.name()).changed() {
+                    match enabled {
+                        true => self.archetypes.push(archetype),
+                        false => self.archetypes.retain(|other| *other != archetype),
+                    }
+                }
+            }
+        });
 
         ui.separator();
         ui.heading("Enemy spawner progression");
@@ -173,3 +303,46 @@ impl EnemySpawner {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waves_spawn_on_their_interval() {
+        let wave = Wave {
+            start_secs: 10.,
+            count: 3,
+            interval_secs: 2.,
+            ..Wave::default()
+        };
+        assert_eq!(wave.due_count(0.), 0);
+        assert_eq!(wave.due_count(9.9), 0);
+        assert_eq!(wave.due_count(10.), 1);
+        assert_eq!(wave.due_count(11.9), 1);
+        assert_eq!(wave.due_count(12.), 2);
+        assert_eq!(wave.due_count(14.), 3);
+        assert_eq!(wave.due_count(1000.), 3);
+    }
+
+    #[test]
+    fn all_waves_spawned_once_every_count_is_reached() {
+        let waves = [
+            Wave {
+                count: 2,
+                ..Wave::default()
+            },
+            Wave {
+                count: 1,
+                ..Wave::default()
+            },
+        ];
+        let mut enemy_spawner = EnemySpawner::default();
+        assert!(!enemy_spawner.all_waves_spawned(&[]));
+        assert!(!enemy_spawner.all_waves_spawned(&waves));
+        enemy_spawner.spawned_per_wave = vec![2, 0];
+        assert!(!enemy_spawner.all_waves_spawned(&waves));
+        enemy_spawner.spawned_per_wave = vec![2, 1];
+        assert!(enemy_spawner.all_waves_spawned(&waves));
+    }
+}
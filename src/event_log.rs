@@ -0,0 +1,262 @@
+use comfy::*;
+use serde::Serialize;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventLogFormat {
+    JsonLines,
+    Csv,
+}
+
+impl EventLogFormat {
+    pub const ALL: [EventLogFormat; 2] = [EventLogFormat::JsonLines, EventLogFormat::Csv];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventLogFormat::JsonLines => "JSON lines",
+            EventLogFormat::Csv => "CSV",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EventLogFormat::JsonLines => "jsonl",
+            EventLogFormat::Csv => "csv",
+        }
+    }
+
+    /// CSV for `.csv` files, JSON lines otherwise
+    pub fn from_path(path: &str) -> Self {
+        match path.ends_with(".csv") {
+            true => EventLogFormat::Csv,
+            false => EventLogFormat::JsonLines,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct LoggedEvent {
+    pub time_secs: f32,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
+
+const CSV_HEADER: &str =
+    "time_secs,type,archetype,trap_kind,tile_x,tile_y,damage,amount,reason,index,x,y";
+
+impl LoggedEvent {
+    /// One row under `CSV_HEADER`, with empty cells for fields the event does not have
+    fn to_csv_row(&self) -> String {
+        let mut archetype = String::new();
+        let mut source = None;
+        let mut damage = String::new();
+        let mut amount = String::new();
+        let mut reason = String::new();
+        let mut index = String::new();
+        let mut position = None;
+        match &self.event {
+            GameEvent::EnemySpawned { archetype: spawned } => archetype = spawned.name().into(),
+            GameEvent::EnemyKilled {
+                archetype: killed,
+                by,
+                gold,
+                position: at,
+            } => {
+                archetype = killed.name().into();
+                source = *by;
+                amount = gold.to_string();
+                position = Some(*at);
+            }
+            GameEvent::TrapBuilt {
+                source: changed,
+                gold_spent,
+            }
+            | GameEvent::TrapUpgraded {
+                source: changed,
+                gold_spent,
+            } => {
+                source = Some(*changed);
+                amount = gold_spent.to_string();
+            }
+            GameEvent::TrapRemoved {
+                source: changed,
+                gold_refunded,
+            }
+            | GameEvent::TrapSold {
+                source: changed,
+                gold_refunded,
+            } => {
+                source = Some(*changed);
+                amount = gold_refunded.to_string();
+            }
+            GameEvent::TrapTriggered {
+                source: triggered,
+                targets_hit,
+            } => {
+                source = Some(*triggered);
+                amount = targets_hit.to_string();
+            }
+            GameEvent::DamageDealt {
+                source: dealt_by,
+                damage: dealt,
+                position: at,
+            } => {
+                source = Some(*dealt_by);
+                damage = dealt.to_string();
+                position = Some(*at);
+            }
+            GameEvent::GoldChanged {
+                amount: changed,
+                reason: because,
+            } => {
+                amount = changed.to_string();
+                reason = format!("{:?}", because);
+            }
+            GameEvent::PlayerDamaged { damage: taken } => damage = taken.to_string(),
+            GameEvent::WaveStarted { index: started } => index = started.to_string(),
+            GameEvent::GameOver | GameEvent::Victory => {}
+        }
+        [
+            self.time_secs.to_string(),
+            self.event.name().to_string(),
+            archetype,
+            source.map_or(String::new(), |source| source.trap_kind.name().into()),
+            source.map_or(String::new(), |source| source.tile.x.to_string()),
+            source.map_or(String::new(), |source| source.tile.y.to_string()),
+            damage,
+            amount,
+            reason,
+            index,
+            position.map_or(String::new(), |position| position.x.to_string()),
+            position.map_or(String::new(), |position| position.y.to_string()),
+        ]
+        .join(",")
+    }
+}
+
+/// Every event of the current run, for balance analysis outside of the game.
+/// Not saved with the run, a continued run is logged from where it was loaded.
+#[derive(Default)]
+pub struct EventLog {
+    pub entries: Vec<LoggedEvent>,
+}
+
+impl EventLog {
+    pub fn on_event(&mut self, time_secs: f32, event: &GameEvent) {
+        self.entries.push(LoggedEvent {
+            time_secs,
+            event: event.clone(),
+        });
+    }
+}
+
+impl EventLog {
+    pub fn export(&self, format: EventLogFormat) -> Result<String> {
+        let lines = match format {
+            EventLogFormat::JsonLines => self
+                .entries
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<_>, _>>()?,
+            EventLogFormat::Csv => std::iter::once(CSV_HEADER.to_string())
+                .chain(self.entries.iter().map(LoggedEvent::to_csv_row))
+                .collect(),
+        };
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+impl GameState {
+    /// Reads the path given by `--event-log <path>` on the command line, if any
+    pub fn load_event_log_path_from_args(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.event_log_path = std::env::args()
+                .skip_while(|arg| arg != "--event-log")
+                .nth(1);
+        }
+    }
+
+    /// Writes the event log next to the path given on the command line, into a new
+    /// file for every run. Called when the run ends, or on quitting before that.
+    pub fn write_event_log(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = self.event_log_path.as_ref() {
+            if self.event_log.entries.is_empty() {
+                return;
+            }
+            let run_path = run_log_path(path, self.seed);
+            let result = self
+                .event_log
+                .export(EventLogFormat::from_path(path))
+                .and_then(|log| Ok(std::fs::write(&run_path, log)?));
+            if let Err(err) = result {
+                self.toasts.error(format!(
+                    "Failed to write the event log to {}: {}",
+                    run_path.display(),
+                    err
+                ));
+            }
+        }
+    }
+
+    /// Keeps the log of a run left before it ended, finished runs wrote theirs already
+    pub fn write_unfinished_event_log(&mut self) {
+        if !self.is_run_recorded {
+            self.write_event_log();
+        }
+    }
+
+    pub fn event_log_debug_ui(&mut self, ui: &mut egui::Ui) {
+        #[cfg(target_arch = "wasm32")]
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Event log ({} events):",
+                self.event_log.entries.len()
+            ));
+            for format in EventLogFormat::ALL {
+                if ui.button(format!("Download {}", format.name())).clicked() {
+                    let file_name = format!("trapify_events.{}", format.extension());
+                    let result = self
+                        .event_log
+                        .export(format)
+                        .and_then(|log| download(&file_name, &log));
+                    if let Err(err) = result {
+                        self.toasts
+                            .error(format!("Failed to download the event log: {}", err));
+                    }
+                }
+            }
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = self.event_log_path.as_ref() {
+            ui.label(format!(
+                "Event log ({} events) is written next to {} when the run ends",
+                self.event_log.entries.len(),
+                path
+            ));
+        }
+    }
+}
+
+/// `events.jsonl` becomes `events_<seed>.jsonl`, numbered further when that file
+/// exists already, so earlier runs and sessions are never overwritten
+#[cfg(not(target_arch = "wasm32"))]
+fn run_log_path(path: &str, seed: u64) -> std::path::PathBuf {
+    let path = std::path::Path::new(path);
+    let stem = path
+        .file_stem()
+        .map_or("events".into(), |stem| stem.to_string_lossy());
+    let extension = path
+        .extension()
+        .map_or("jsonl".into(), |extension| extension.to_string_lossy());
+    (1..)
+        .map(|index| match index {
+            1 => format!("{}_{}.{}", stem, seed, extension),
+            _ => format!("{}_{}_{}.{}", stem, seed, index, extension),
+        })
+        .map(|file_name| path.with_file_name(file_name))
+        .find(|run_path| !run_path.exists())
+        .expect("Some file name is not taken yet")
+}
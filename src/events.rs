@@ -0,0 +1,145 @@
+use comfy::*;
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GoldChangeReason {
+    Kill,
+    Interest,
+    TrapBuilt,
+    TrapUpgraded,
+    TrapSold,
+    /// Building, upgrading or selling undone
+    Refund,
+    Cheat,
+}
+
+/// Everything notable happening in a run, emitted by the core systems
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GameEvent {
+    EnemySpawned {
+        archetype: EnemyArchetype,
+    },
+    EnemyKilled {
+        archetype: EnemyArchetype,
+        by: Option<DamageSource>,
+        gold: f32,
+        #[serde(with = "vec2_serde")]
+        position: Vec2,
+    },
+    TrapBuilt {
+        source: DamageSource,
+        gold_spent: f32,
+    },
+    /// Building undone
+    TrapRemoved {
+        source: DamageSource,
+        gold_refunded: f32,
+    },
+    /// Negative gold when the upgrade was undone
+    TrapUpgraded {
+        source: DamageSource,
+        gold_spent: f32,
+    },
+    /// Negative gold when the sale was undone
+    TrapSold {
+        source: DamageSource,
+        gold_refunded: f32,
+    },
+    /// Cooldown finished, whether or not any enemy was in reach
+    TrapTriggered {
+        source: DamageSource,
+        targets_hit: usize,
+    },
+    DamageDealt {
+        source: DamageSource,
+        damage: f32,
+        #[serde(with = "vec2_serde")]
+        position: Vec2,
+    },
+    GoldChanged {
+        amount: f32,
+        reason: GoldChangeReason,
+    },
+    PlayerDamaged {
+        damage: f32,
+    },
+    WaveStarted {
+        index: usize,
+    },
+    GameOver,
+    /// Every wave of a finite map was defeated
+    Victory,
+}
+
+impl GameEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameEvent::EnemySpawned { .. } => "EnemySpawned",
+            GameEvent::EnemyKilled { .. } => "EnemyKilled",
+            GameEvent::TrapBuilt { .. } => "TrapBuilt",
+            GameEvent::TrapRemoved { .. } => "TrapRemoved",
+            GameEvent::TrapUpgraded { .. } => "TrapUpgraded",
+            GameEvent::TrapSold { .. } => "TrapSold",
+            GameEvent::TrapTriggered { .. } => "TrapTriggered",
+            GameEvent::DamageDealt { .. } => "DamageDealt",
+            GameEvent::GoldChanged { .. } => "GoldChanged",
+            GameEvent::PlayerDamaged { .. } => "PlayerDamaged",
+            GameEvent::WaveStarted { .. } => "WaveStarted",
+            GameEvent::GameOver => "GameOver",
+            GameEvent::Victory => "Victory",
+        }
+    }
+}
+
+/// Hands an event to a system living somewhere in the state, which registers it
+/// with `EventBus::subscribe`, so the core systems emitting events never need to know about it
+pub type EventHandler = fn(&mut GameState, f32, &GameEvent);
+
+/// Events emitted during the current tick, dispatched and cleared at its end
+#[derive(Default)]
+pub struct EventBus {
+    /// Each with the run time it was emitted at
+    events: Vec<(f32, GameEvent)>,
+    /// Run time of the simulation step currently running
+    time_secs: f32,
+    handlers: Vec<EventHandler>,
+}
+
+impl EventBus {
+    pub fn subscribe(&mut self, handler: EventHandler) {
+        self.handlers.push(handler);
+    }
+
+    /// Keeps the subscriptions for the next run, the events of the old one are dropped
+    pub fn for_next_run(&mut self) -> Self {
+        Self {
+            handlers: std::mem::take(&mut self.handlers),
+            ..Self::default()
+        }
+    }
+
+    /// Called with every simulation step, so fast forwarded steps are told apart
+    pub fn set_time(&mut self, time_secs: f32) {
+        self.time_secs = time_secs;
+    }
+
+    pub fn emit(&mut self, event: GameEvent) {
+        self.events.push((self.time_secs, event));
+    }
+}
+
+impl GameState {
+    /// Hands the events of this tick to every subscriber, should run last
+    pub fn dispatch_events(&mut self) {
+        let events = std::mem::take(&mut self.event_bus.events);
+        let handlers = self.event_bus.handlers.clone();
+        for (time_secs, event) in events.iter() {
+            for handler in handlers.iter() {
+                handler(self, *time_secs, event);
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use comfy::*;
+
+use crate::*;
+
+const LIFETIME_SECS: f32 = 1.;
+/// World units per second the text rises while fading out
+const RISE_SPEED: f32 = 0.5;
+/// DOT damage is dealt every frame, so it is summed up per trap before being shown
+const DOT_AGGREGATION_SECS: f32 = 0.5;
+
+pub struct FloatingText {
+    pub text: String,
+    pub position: Vec2,
+    pub color: Color,
+    pub age_secs: f32,
+}
+
+struct PendingDotDamage {
+    damage: f32,
+    position: Vec2,
+    started_secs: f32,
+}
+
+/// World-space text popping up over damaged and killed enemies
+#[derive(Default)]
+pub struct FloatingTexts {
+    texts: Vec<FloatingText>,
+    pending_dot_damage: HashMap<DamageSource, PendingDotDamage>,
+    /// Where gold without a place in the world, like interest, pops up
+    payout_position: Vec2,
+}
+
+impl FloatingTexts {
+    pub fn spawn(&mut self, text: String, position: Vec2, color: Color) {
+        self.texts.push(FloatingText {
+            text,
+            position,
+            color,
+            age_secs: 0.,
+        });
+    }
+
+    pub fn update(&mut self, delta: f32, now_secs: f32) {
+        self.texts.retain_mut(|floating_text| {
+            floating_text.age_secs += delta;
+            floating_text.position.y += RISE_SPEED * delta;
+            floating_text.age_secs < LIFETIME_SECS
+        });
+
+        let finished = self
+            .pending_dot_damage
+            .iter()
+            .filter(|(_, pending)| now_secs - pending.started_secs >= DOT_AGGREGATION_SECS)
+            .map(|(source, _)| *source)
+            .collect_vec();
+        for source in finished {
+            if let Some(pending) = self.pending_dot_damage.remove(&source) {
+                self.spawn(
+                    format!("{:.1}", pending.damage),
+                    pending.position,
+                    source.trap_kind.color(),
+                );
+            }
+        }
+    }
+
+    pub fn draw(&self) {
+        for floating_text in self.texts.iter() {
+            let fade = 1. - floating_text.age_secs / LIFETIME_SECS;
+            draw_text(
+                &floating_text.text,
+                floating_text.position,
+                floating_text.color.alpha(fade),
+                TextAlign::Center,
+            );
+        }
+    }
+}
+
+impl FloatingTexts {
+    pub fn on_event(&mut self, time_secs: f32, event: &GameEvent) {
+        match event {
+            GameEvent::DamageDealt {
+                source,
+                damage,
+                position,
+            } if *damage > 0. => match source.trap_kind {
+                TrapKind::DamageOverTime => {
+                    let pending =
+                        self.pending_dot_damage
+                            .entry(*source)
+                            .or_insert(PendingDotDamage {
+                                damage: 0.,
+                                position: *position,
+                                started_secs: time_secs,
+                            });
+                    pending.damage += damage;
+                    pending.position = *position;
+                }
+                _ => self.spawn(format!("{:.0}", damage), *position, RED),
+            },
+            GameEvent::EnemyKilled { gold, position, .. } => {
+                self.spawn(format!("+{:.1} gold", gold), *position, GOLD)
+            }
+            GameEvent::GoldChanged {
+                amount,
+                reason: GoldChangeReason::Interest,
+            } => self.spawn(
+                format!("+{:.1} gold interest", amount),
+                self.payout_position,
+                GOLD,
+            ),
+            _ => {}
+        }
+    }
+}
+
+impl GameState {
+    pub fn update_floating_texts(&mut self) {
+        if self.is_paused {
+            return;
+        }
+        self.floating_texts.payout_position =
+            Vec2::new(self.tile_map.width() / 2., self.tile_map.height());
+        self.floating_texts
+            .update(self.delta, self.run_length_seconds);
+    }
+}
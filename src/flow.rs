@@ -0,0 +1,320 @@
+use comfy::{egui::Align2, *};
+
+use crate::{tile_map::BUNDLED_MAPS, *};
+
+const MENU_BUTTON_WIDTH: f32 = 200.;
+
+/// Which screen is shown, the simulation only runs while playing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Screen {
+    #[default]
+    MainMenu,
+    NewRun,
+    Settings,
+    Playing,
+    PauseMenu,
+    Victory,
+    GameOver,
+}
+
+/// Choices for the next run, made in the new run screen
+pub struct NewRunSetup {
+    pub mode: GameMode,
+    /// Index into `BUNDLED_MAPS`
+    pub map_index: usize,
+    /// Left empty for a random seed
+    pub seed: String,
+}
+
+impl Default for NewRunSetup {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::Normal,
+            map_index: 0,
+            seed: String::new(),
+        }
+    }
+}
+
+/// Moves between the menus and the run, kept across runs
+#[derive(Default)]
+pub struct GameFlow {
+    pub screen: Screen,
+    /// Screen the settings go back to
+    settings_return: Screen,
+    pub new_run: NewRunSetup,
+    /// The state holds a run worth saving, rather than the one shown behind the main menu
+    pub is_run_started: bool,
+    /// Looked up when entering the main menu, so storage is not read every frame
+    has_saved_run: bool,
+    /// Comfy can only quit from `update`, which checks this
+    pub should_quit: bool,
+}
+
+fn menu_button(ui: &mut egui::Ui, text: &str) -> bool {
+    ui.add_sized([MENU_BUTTON_WIDTH, 0.], egui::Button::new(text))
+        .clicked()
+}
+
+fn menu_window(title: &str) -> egui::Window<'_> {
+    egui::Window::new(title)
+        .anchor(Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+}
+
+impl GameFlow {
+    /// Whether the screen belongs to a run, which is shown with the HUD
+    pub fn is_in_run(&self) -> bool {
+        match self.screen {
+            Screen::MainMenu | Screen::NewRun => false,
+            Screen::Settings => self.settings_return == Screen::PauseMenu,
+            Screen::Playing | Screen::PauseMenu | Screen::Victory | Screen::GameOver => true,
+        }
+    }
+}
+
+impl GameState {
+    /// Called by every way of starting or loading a run
+    pub fn start_playing(&mut self) {
+        self.flow.screen = Screen::Playing;
+        self.flow.is_run_started = true;
+        self.event_bus.set_time(self.run_length_seconds);
+    }
+
+    pub fn open_main_menu(&mut self) {
+        self.flow.screen = Screen::MainMenu;
+        self.flow.has_saved_run = storage()
+            .and_then(|storage| storage.read(SAVE_KEY))
+            .is_ok_and(|save| save.is_some());
+    }
+
+    fn open_settings(&mut self) {
+        self.flow.settings_return = self.flow.screen;
+        self.flow.screen = Screen::Settings;
+    }
+
+    /// Leaves the run for the main menu, saving it to be continued
+    fn quit_to_main_menu(&mut self) {
+        self.autosave();
+        self.flow.is_run_started = false;
+        self.open_main_menu();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn quit_game(&mut self) {
+        self.autosave();
+        self.flow.is_run_started = false;
+        self.flow.should_quit = true;
+    }
+
+    /// Escape cancels building first, so the pause menu only opens when not building
+    pub fn flow_inputs(&mut self) {
+        if !self.keybindings.is_pressed(Action::Cancel) {
+            return;
+        }
+        match self.flow.screen {
+            Screen::Playing if self.build_mode.is_none() && self.editor.is_none() => {
+                self.flow.screen = Screen::PauseMenu;
+            }
+            Screen::PauseMenu => self.flow.screen = Screen::Playing,
+            Screen::NewRun => self.open_main_menu(),
+            Screen::Settings => self.flow.screen = self.flow.settings_return,
+            _ => {}
+        }
+    }
+
+    /// Shows the result once the run ended, the finished run is no longer worth continuing
+    pub fn update_flow(&mut self) {
+        if self.flow.screen != Screen::Playing || !self.is_game_over || self.editor.is_some() {
+            return;
+        }
+        self.flow.screen = match self.player.hp.is_dead() {
+            true => Screen::GameOver,
+            false => Screen::Victory,
+        };
+        self.flow.is_run_started = false;
+        if let Err(err) = storage().and_then(|storage| storage.remove(SAVE_KEY)) {
+            self.toasts
+                .error(format!("Failed to remove the finished run: {}", err));
+        }
+    }
+
+    pub fn flow_ui(&mut self) {
+        match self.flow.screen {
+            Screen::MainMenu => self.main_menu_ui(),
+            Screen::NewRun => self.new_run_ui(),
+            Screen::Settings => self.settings_ui(),
+            Screen::Playing => {}
+            Screen::PauseMenu => self.pause_menu_ui(),
+            Screen::Victory | Screen::GameOver => self.run_end_ui(),
+        }
+    }
+
+    fn main_menu_ui(&mut self) {
+        menu_window("Trapify").show(egui(), |ui| {
+            ui.vertical_centered(|ui| {
+                if menu_button(ui, "New run") {
+                    self.flow.screen = Screen::NewRun;
+                }
+                let can_continue = self.flow.has_saved_run;
+                let continue_button = ui
+                    .add_enabled(
+                        can_continue,
+                        egui::Button::new("Continue").min_size(egui::vec2(MENU_BUTTON_WIDTH, 0.)),
+                    )
+                    .on_disabled_hover_text("There is no saved run");
+                if continue_button.clicked() {
+                    if let Err(err) = self.load_run() {
+                        self.toasts.error(format!("Failed to load: {}", err));
+                    }
+                }
+                if menu_button(ui, "High scores") {
+                    self.show_high_scores = true;
+                }
+                if menu_button(ui, "Settings") {
+                    self.open_settings();
+                }
+                // Browser tabs are closed by the player instead
+                #[cfg(not(target_arch = "wasm32"))]
+                if menu_button(ui, "Quit") {
+                    self.quit_game();
+                }
+            });
+        });
+    }
+
+    fn new_run_ui(&mut self) {
+        let mut should_start = false;
+        menu_window("New run").show(egui(), |ui| {
+            let new_run = &mut self.flow.new_run;
+            egui::Grid::new("New run").num_columns(2).show(ui, |ui| {
+                ui.label("Mode:");
+                ui.horizontal(|ui| {
+                    for mode in GameMode::ALL {
+                        ui.selectable_value(&mut new_run.mode, mode, mode.name());
+                    }
+                });
+                ui.end_row();
+                ui.label("Map:");
+                ui.horizontal_wrapped(|ui| {
+                    for (index, tile_map) in BUNDLED_MAPS.iter().enumerate() {
+                        ui.selectable_value(&mut new_run.map_index, index, &tile_map.name);
+                    }
+                });
+                ui.end_row();
+                ui.label("Seed:");
+                ui.add(egui::TextEdit::singleline(&mut new_run.seed).hint_text("Random"));
+                ui.end_row();
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Start").clicked() {
+                    should_start = true;
+                }
+                if ui.button("Back").clicked() {
+                    self.open_main_menu();
+                }
+            });
+        });
+        if !should_start {
+            return;
+        }
+        let new_run = &self.flow.new_run;
+        let seed = match new_run.seed.trim() {
+            "" => thread_rng().gen(),
+            seed => match seed.parse() {
+                Ok(seed) => seed,
+                Err(_) => {
+                    self.toasts
+                        .warning("The seed has to be a whole non-negative number");
+                    return;
+                }
+            },
+        };
+        let Some(tile_map) = BUNDLED_MAPS.get(new_run.map_index).cloned() else {
+            return;
+        };
+        let mode = new_run.mode;
+        self.restart_with(tile_map, seed, mode);
+    }
+
+    fn settings_ui(&mut self) {
+        menu_window("Settings").show(egui(), |ui| {
+            self.audio_debug_ui(ui);
+            ui.separator();
+            ui.horizontal(|ui| {
+                if !self.touch.is_touch_device && ui.button("Keybindings").clicked() {
+                    self.keybindings.show_settings = !self.keybindings.show_settings;
+                }
+                if ui.button("Back").clicked() {
+                    self.flow.screen = self.flow.settings_return;
+                }
+            });
+        });
+    }
+
+    fn pause_menu_ui(&mut self) {
+        menu_window("Paused").show(egui(), |ui| {
+            ui.vertical_centered(|ui| {
+                if menu_button(ui, "Resume") {
+                    self.flow.screen = Screen::Playing;
+                }
+                if menu_button(ui, "Restart") {
+                    self.restart();
+                }
+                if menu_button(ui, "Settings") {
+                    self.open_settings();
+                }
+                if menu_button(ui, "Save and quit to menu") {
+                    self.quit_to_main_menu();
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if menu_button(ui, "Save and quit game") {
+                    self.quit_game();
+                }
+            });
+        });
+    }
+
+    /// Victory or game over, with the statistics of the run
+    fn run_end_ui(&mut self) {
+        let title = match self.flow.screen {
+            Screen::Victory => "VICTORY",
+            _ => "GAME OVER",
+        };
+        menu_window(title).show(egui(), |ui| {
+            match self.flow.screen {
+                Screen::Victory => ui.label(format!(
+                    "You have defeated every wave of {} in {}",
+                    self.tile_map.name,
+                    self.run_length_formatted()
+                )),
+                _ => ui.label(format!(
+                    "You have lasted for {}",
+                    self.run_length_formatted()
+                )),
+            };
+            ui.label(format!(
+                "Kills: {}\tGold earned: {:.0}\tTraps built: {}",
+                self.statistics.kills, self.statistics.gold_earned, self.statistics.traps_built
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("Restart game").clicked() {
+                    self.restart()
+                }
+                if ui.button("Restart with the same seed").clicked() {
+                    let tile_map = std::mem::take(&mut self.tile_map);
+                    self.restart_with(tile_map, self.seed, self.mode);
+                }
+                if ui.button("High scores").clicked() {
+                    self.show_high_scores = true;
+                }
+                if ui.button("Main menu").clicked() {
+                    self.open_main_menu();
+                }
+            });
+            ui.separator();
+            self.statistics_ui(ui);
+        });
+    }
+}
@@ -0,0 +1,91 @@
+use comfy::*;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GameSpeed {
+    Half,
+    #[default]
+    Normal,
+    Double,
+    Quadruple,
+}
+
+impl GameSpeed {
+    pub const ALL: [GameSpeed; 4] = [
+        GameSpeed::Half,
+        GameSpeed::Normal,
+        GameSpeed::Double,
+        GameSpeed::Quadruple,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameSpeed::Half => "0.5x",
+            GameSpeed::Normal => "1x",
+            GameSpeed::Double => "2x",
+            GameSpeed::Quadruple => "4x",
+        }
+    }
+
+    pub fn multiplier(&self) -> f32 {
+        match self {
+            GameSpeed::Half => 0.5,
+            GameSpeed::Normal => 1.,
+            GameSpeed::Double => 2.,
+            GameSpeed::Quadruple => 4.,
+        }
+    }
+
+    /// Fast forward runs several simulation steps per frame instead of one longer step,
+    /// so that enemies do not skip over traps and tiles
+    pub fn steps_per_frame(&self) -> u32 {
+        self.multiplier().ceil() as u32
+    }
+
+    pub fn slower(&self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|speed| speed == self)
+            .unwrap_or(1);
+        Self::ALL[index.saturating_sub(1)]
+    }
+
+    pub fn faster(&self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|speed| speed == self)
+            .unwrap_or(1);
+        Self::ALL[(index + 1).min(Self::ALL.len() - 1)]
+    }
+}
+
+impl GameState {
+    /// Length of a single simulation step, the frame is split into `steps_per_frame` of them
+    pub fn simulation_delta(&self) -> f32 {
+        delta() * self.game_speed.multiplier() / self.game_speed.steps_per_frame() as f32
+    }
+
+    pub fn game_speed_inputs(&mut self) {
+        if self.keybindings.is_pressed(Action::SlowDown) {
+            self.game_speed = self.game_speed.slower();
+        }
+        if self.keybindings.is_pressed(Action::SpeedUp) {
+            self.game_speed = self.game_speed.faster();
+        }
+    }
+
+    pub fn game_speed_ui(&mut self, ui: &mut egui::Ui) {
+        match self.touch.is_touch_device {
+            true => ui.label("Speed:"),
+            false => ui.label(format!(
+                "Speed ({}/{}):",
+                self.keybindings.hint(Action::SlowDown),
+                self.keybindings.hint(Action::SpeedUp)
+            )),
+        };
+        for game_speed in GameSpeed::ALL {
+            ui.selectable_value(&mut self.game_speed, game_speed, game_speed.name());
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use comfy::{egui::Align2, *};
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+pub const HIGH_SCORES_KEY: &str = "high_scores";
+const MAX_HIGH_SCORES: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    Normal,
+    /// Allows tweaking the player and the enemy spawner through the debug panels
+    Sandbox,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 2] = [GameMode::Normal, GameMode::Sandbox];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Normal => "Normal",
+            GameMode::Sandbox => "Sandbox",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunOutcome {
+    /// Runs recorded before victories existed all ended this way
+    #[default]
+    Died,
+    /// Every wave of a map with waves was defeated
+    Won,
+}
+
+impl RunOutcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunOutcome::Died => "Died",
+            RunOutcome::Won => "Won",
+        }
+    }
+}
+
+/// Everything needed to show a finished run and to replay it with the same seed
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    #[serde(default)]
+    pub outcome: RunOutcome,
+    pub run_length_seconds: f32,
+    pub seed: u64,
+    pub mode: GameMode,
+    pub statistics: RunStatistics,
+    pub tile_map: TileMap,
+    pub layout: Vec<(TileMapPos, TrapKind)>,
+}
+
+/// Finished runs, victories first with the fastest on top, then the longest lasting deaths
+#[derive(Default, Serialize, Deserialize)]
+pub struct HighScores {
+    pub runs: Vec<RunSummary>,
+}
+
+impl HighScores {
+    pub fn load() -> Result<Self> {
+        Ok(storage()?.read_json(HIGH_SCORES_KEY)?.unwrap_or_default())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        storage()?.write_json(HIGH_SCORES_KEY, self)
+    }
+
+    pub fn record(&mut self, run_summary: RunSummary) {
+        self.runs.push(run_summary);
+        self.runs.sort_by_key(|run| match run.outcome {
+            RunOutcome::Won => (0, OrderedFloat(run.run_length_seconds)),
+            RunOutcome::Died => (1, OrderedFloat(-run.run_length_seconds)),
+        });
+        self.runs.truncate(MAX_HIGH_SCORES);
+    }
+}
+
+impl GameState {
+    pub fn run_summary(&self) -> RunSummary {
+        let mut layout = self
+            .trap_tiles
+            .iter()
+            .filter_map(|(tile_map_pos, trap_tile)| match trap_tile {
+                TrapTile::Built { trap, .. } => Some((*tile_map_pos, trap.kind())),
+                TrapTile::ToBeBuild(_) => None,
+            })
+            .collect_vec();
+        layout.sort_by_key(|(tile_map_pos, _)| (tile_map_pos.x, tile_map_pos.y));
+        let outcome = match self.player.hp.is_dead() {
+            true => RunOutcome::Died,
+            false => RunOutcome::Won,
+        };
+        RunSummary {
+            outcome,
+            run_length_seconds: self.run_length_seconds,
+            seed: self.seed,
+            mode: self.mode,
+            statistics: self.statistics.clone(),
+            tile_map: self.tile_map.clone(),
+            layout,
+        }
+    }
+
+    /// Records the run once it ended, after the events of its final tick were dispatched
+    pub fn record_finished_run(&mut self) {
+        if !self.is_game_over || self.is_run_recorded {
+            return;
+        }
+        self.is_run_recorded = true;
+        self.record_high_score();
+        self.write_event_log();
+    }
+
+    fn record_high_score(&mut self) {
+        self.high_scores.record(self.run_summary());
+        if let Err(err) = self.high_scores.save() {
+            self.toasts
+                .error(format!("Failed to save high scores: {}", err));
+        }
+    }
+
+    pub fn high_scores_ui(&mut self) {
+        if !self.show_high_scores {
+            return;
+        }
+        let mut replay = None;
+        egui::Window::new("High scores")
+            .anchor(Align2::CENTER_CENTER, [0., 0.])
+            .collapsible(false)
+            .open(&mut self.show_high_scores)
+            .show(egui(), |ui| {
+                if self.high_scores.runs.is_empty() {
+                    ui.label("No finished runs yet");
+                    return;
+                }
+                egui::Grid::new("High scores").striped(true).show(ui, |ui| {
+                    ui.label("Outcome");
+                    ui.label("Time");
+                    ui.label("Map");
+                    ui.label("Mode");
+                    ui.label("Seed");
+                    ui.label("Kills");
+                    ui.label("Gold earned");
+                    ui.label("Traps built");
+                    ui.label("Final layout");
+                    ui.end_row();
+                    for (index, run) in self.high_scores.runs.iter().enumerate() {
+                        ui.label(run.outcome.name());
+                        ui.label(format_run_length(run.run_length_seconds));
+                        ui.label(&run.tile_map.name);
+                        ui.label(run.mode.name());
+                        ui.label(run.seed.to_string());
+                        ui.label(run.statistics.kills.to_string());
+                        ui.label(format!("{:.0}", run.statistics.gold_earned));
+                        ui.label(run.statistics.traps_built.to_string());
+                        ui.label(layout_summary(&run.layout));
+                        if ui.button("Replay seed").clicked() {
+                            replay = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        if let Some(index) = replay {
+            let run = self.high_scores.runs[index].clone();
+            self.restart_with(run.tile_map, run.seed, run.mode);
+        }
+    }
+}
+
+/// Number of built traps of each kind, e.g. "3 Simple, 1 Wall"
+fn layout_summary(layout: &[(TileMapPos, TrapKind)]) -> String {
+    TrapKind::ALL
+        .iter()
+        .filter_map(|trap_kind| {
+            let count = layout.iter().filter(|(_, kind)| kind == trap_kind).count();
+            (count > 0).then(|| format!("{} {}", count, trap_kind.name()))
+        })
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(outcome: RunOutcome, run_length_seconds: f32) -> RunSummary {
+        RunSummary {
+            outcome,
+            run_length_seconds,
+            seed: 0,
+            mode: GameMode::Normal,
+            statistics: RunStatistics::default(),
+            tile_map: TileMap::default(),
+            layout: vec![],
+        }
+    }
+
+    #[test]
+    fn fastest_victories_rank_above_longest_deaths() {
+        let mut high_scores = HighScores::default();
+        for (outcome, run_length_seconds) in [
+            (RunOutcome::Died, 30.),
+            (RunOutcome::Won, 200.),
+            (RunOutcome::Died, 90.),
+            (RunOutcome::Won, 150.),
+        ] {
+            high_scores.record(run(outcome, run_length_seconds));
+        }
+        let ranking = high_scores
+            .runs
+            .iter()
+            .map(|run| (run.outcome, run.run_length_seconds))
+            .collect_vec();
+        assert_eq!(
+            ranking,
+            [
+                (RunOutcome::Won, 150.),
+                (RunOutcome::Won, 200.),
+                (RunOutcome::Died, 90.),
+                (RunOutcome::Died, 30.),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_the_best_runs_are_kept() {
+        let mut high_scores = HighScores::default();
+        for run_length_seconds in 0..MAX_HIGH_SCORES + 5 {
+            high_scores.record(run(RunOutcome::Died, run_length_seconds as f32));
+        }
+        assert_eq!(high_scores.runs.len(), MAX_HIGH_SCORES);
+        assert_eq!(high_scores.runs.last().unwrap().run_length_seconds, 5.);
+    }
+}
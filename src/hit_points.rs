@@ -1,3 +1,6 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
 pub struct HitPoints {
     maximum: f32,
     current: f32,
@@ -15,8 +18,15 @@ impl HitPoints {
         self.current / self.maximum
     }
 
-    pub fn take_damage(&mut self, damage: f32) {
-        self.current -= damage
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    /// Returns the damage actually taken, not counting overkill
+    pub fn take_damage(&mut self, damage: f32) -> f32 {
+        let taken = damage.min(self.current.max(0.));
+        self.current -= damage;
+        taken
     }
 
     pub fn is_dead(&self) -> bool {
@@ -0,0 +1,219 @@
+use comfy::*;
+
+use crate::*;
+
+const ICON_SIZE: f32 = 32.;
+const HP_BAR_WIDTH: f32 = 200.;
+const INTEREST_BAR_WIDTH: f32 = 160.;
+
+/// Player-facing UI, kept across runs
+#[derive(Default)]
+pub struct Hud {
+    pub show_debug_panels: bool,
+    /// Loaded the first time the build bar is shown, egui needs its own copy of the sprites
+    trap_icons: Option<HashMap<TrapKind, egui::TextureHandle>>,
+}
+
+impl Hud {
+    fn trap_icon(&mut self, trap_kind: TrapKind) -> Option<egui::TextureId> {
+        let trap_icons = self.trap_icons.get_or_insert_with(|| {
+            TrapKind::ALL
+                .into_iter()
+                .filter_map(|trap_kind| {
+                    let image = sprite_first_frame(trap_kind.sprite_name())?;
+                    let color_image = egui::ColorImage::from_rgba_unmultiplied(
+                        [image.width() as usize, image.height() as usize],
+                        image.as_raw(),
+                    );
+                    let texture = egui().load_texture(
+                        trap_kind.sprite_name(),
+                        color_image,
+                        egui::TextureOptions::NEAREST,
+                    );
+                    Some((trap_kind, texture))
+                })
+                .collect()
+        });
+        trap_icons.get(&trap_kind).map(|texture| texture.id())
+    }
+}
+
+impl GameState {
+    pub fn hud_inputs(&mut self) {
+        if self.keybindings.is_pressed(Action::ToggleDebugPanels) {
+            self.hud.show_debug_panels = !self.hud.show_debug_panels;
+        }
+    }
+
+    pub fn hud_ui(&mut self) {
+        if self.editor.is_some() {
+            return;
+        }
+        // Shown behind the menus of the run, but only usable while playing
+        let is_playing = self.flow.screen == Screen::Playing;
+        // Phones wrap the status and scroll the build bar instead of cutting them off
+        egui::TopBottomPanel::top("hud").show(egui(), |ui| {
+            ui.set_enabled(is_playing);
+            match is_compact_layout() {
+                true => {
+                    ui.horizontal_wrapped(|ui| self.status_ui(ui));
+                }
+                false => {
+                    ui.horizontal(|ui| self.status_ui(ui));
+                }
+            }
+        });
+        egui::TopBottomPanel::bottom("build bar").show(egui(), |ui| {
+            ui.set_enabled(is_playing);
+            match is_compact_layout() {
+                true => {
+                    egui::ScrollArea::horizontal().show(ui, |ui| self.build_bar_ui(ui));
+                }
+                false => self.build_bar_ui(ui),
+            }
+        });
+    }
+
+    fn status_ui(&mut self, ui: &mut egui::Ui) {
+        let bar_scale = match is_compact_layout() {
+            true => 0.5,
+            false => 1.,
+        };
+        ui.label("HP");
+        ui.add(
+            egui::ProgressBar::new(self.player.hp.remaining_fraction().max(0.))
+                .desired_width(HP_BAR_WIDTH * bar_scale)
+                .fill(RED.egui())
+                .text(self.player.hp.as_text()),
+        );
+        ui.separator();
+
+        let gold = &self.player.gold;
+        ui.label(format!("Gold: {:.0}", gold.value));
+        let secs_until_interest =
+            (gold.interest_cooldown.duration() - gold.interest_cooldown.elapsed()).as_secs_f32();
+        ui.add(
+            egui::ProgressBar::new(1. - gold.interest_cooldown.percent_left())
+                .desired_width(INTEREST_BAR_WIDTH * bar_scale)
+                .fill(GOLD.egui())
+                .text(format!(
+                    "+{:.0} in {:.0}s",
+                    gold.interest_to_gain(),
+                    secs_until_interest.ceil()
+                )),
+        );
+        ui.separator();
+
+        ui.label(format!("Time: {}", self.run_length_formatted()));
+        ui.label(self.wave_status());
+        ui.separator();
+
+        self.game_speed_ui(ui);
+        ui.separator();
+
+        if self.is_paused && !self.is_game_over {
+            ui.strong(self.with_key_hint("PAUSED", Action::Pause));
+        }
+        if ui
+            .button(self.with_key_hint("Menu", Action::Cancel))
+            .clicked()
+        {
+            self.flow.screen = Screen::PauseMenu;
+        }
+        // Without a keyboard the debug panels need a button
+        if self.touch.is_touch_device {
+            let pause_text = match self.is_paused {
+                true => "Resume",
+                false => "Pause",
+            };
+            if ui.button(pause_text).clicked() {
+                self.is_paused = !self.is_paused;
+            }
+            ui.toggle_value(&mut self.hud.show_debug_panels, "Debug");
+            return;
+        }
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.weak(format!(
+                "{}: debug panels",
+                self.keybindings.hint(Action::ToggleDebugPanels)
+            ));
+        });
+    }
+
+    fn wave_status(&self) -> String {
+        let waves = &self.tile_map.waves;
+        if waves.is_empty() {
+            return format!(
+                "Enemies grow stronger in {:.0}s",
+                self.enemy_spawner.secs_until_progression().ceil()
+            );
+        }
+        let started = waves
+            .iter()
+            .filter(|wave| wave.start_secs <= self.run_length_seconds)
+            .count();
+        let next_start = waves
+            .iter()
+            .map(|wave| wave.start_secs)
+            .filter(|start_secs| *start_secs > self.run_length_seconds)
+            .min_by_key(|start_secs| OrderedFloat(*start_secs));
+        match next_start {
+            Some(start_secs) => format!(
+                "Wave {}/{}, next in {:.0}s",
+                started,
+                waves.len(),
+                (start_secs - self.run_length_seconds).ceil()
+            ),
+            None => format!("Wave {}/{}, final wave", started, waves.len()),
+        }
+    }
+
+    fn build_bar_ui(&mut self, ui: &mut egui::Ui) {
+        let building = self.build_mode.map(|build_mode| build_mode.trap_kind);
+        ui.horizontal(|ui| {
+            for trap_kind in TrapKind::ALL {
+                let gold_cost = TrapBuilder::default_for(trap_kind).gold_cost();
+                let text = format!(
+                    "{}\n{:.0} gold",
+                    self.with_key_hint(trap_kind.name(), Action::BuildTrap(trap_kind)),
+                    gold_cost
+                );
+                let button = match self.hud.trap_icon(trap_kind) {
+                    Some(texture_id) => egui::Button::image_and_text(
+                        egui::Image::new(egui::load::SizedTexture::new(
+                            texture_id,
+                            egui::vec2(ICON_SIZE, ICON_SIZE),
+                        )),
+                        text,
+                    ),
+                    None => egui::Button::new(text).fill(trap_kind.color().egui()),
+                };
+                let enabled = self.player.gold.value >= gold_cost;
+                let button = button.selected(building == Some(trap_kind));
+                let response = ui
+                    .add_enabled(enabled, button)
+                    .on_disabled_hover_text(format!(
+                        "Not enough gold, {:.0} more needed",
+                        gold_cost - self.player.gold.value
+                    ));
+                if response.clicked() {
+                    self.toggle_build_mode(trap_kind);
+                }
+            }
+            ui.separator();
+            self.undo_ui(ui);
+            ui.separator();
+            match building {
+                Some(_) if self.touch.is_touch_device => {
+                    ui.label("Tap to place, long-press to inspect a tile")
+                }
+                Some(_) => ui.label(format!(
+                    "Click or press {} to place, shift-drag to place along a line, right-click or {} to cancel",
+                    self.keybindings.hint(Action::Confirm),
+                    self.keybindings.hint(Action::Cancel)
+                )),
+                None => ui.label("Pick a trap to build"),
+            };
+        });
+    }
+}
@@ -0,0 +1,462 @@
+use comfy::{egui::Align2, *};
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+pub const KEYBINDINGS_KEY: &str = "keybindings";
+
+/// Keys which can be bound to actions, comfy has no way of listing them
+const BINDABLE_KEYS: [KeyCode; 71] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Num0,
+    KeyCode::Num1,
+    KeyCode::Num2,
+    KeyCode::Num3,
+    KeyCode::Num4,
+    KeyCode::Num5,
+    KeyCode::Num6,
+    KeyCode::Num7,
+    KeyCode::Num8,
+    KeyCode::Num9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Space,
+    KeyCode::Return,
+    KeyCode::Escape,
+    KeyCode::Tab,
+    KeyCode::Backspace,
+    KeyCode::Delete,
+    KeyCode::Insert,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Minus,
+    KeyCode::Equals,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Semicolon,
+    KeyCode::LeftBracket,
+    KeyCode::RightBracket,
+];
+
+/// Default keys picking the traps of `TrapKind::ALL`, in order
+const BUILD_KEYS: [KeyCode; 9] = [
+    KeyCode::Num1,
+    KeyCode::Num2,
+    KeyCode::Num3,
+    KeyCode::Num4,
+    KeyCode::Num5,
+    KeyCode::Num6,
+    KeyCode::Num7,
+    KeyCode::Num8,
+    KeyCode::Num9,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Pause,
+    SlowDown,
+    SpeedUp,
+    MoveSelectionUp,
+    MoveSelectionDown,
+    MoveSelectionLeft,
+    MoveSelectionRight,
+    /// Builds the trap picked in the build mode on the selected tile
+    Confirm,
+    Cancel,
+    BuildTrap(TrapKind),
+    /// Acts on the trap built on the selected tile
+    UpgradeTrap,
+    SellTrap,
+    Undo,
+    Redo,
+    ZoomIn,
+    ZoomOut,
+    ResetCamera,
+    ToggleDebugPanels,
+}
+
+impl Action {
+    pub fn all() -> Vec<Action> {
+        let mut result = vec![
+            Action::Pause,
+            Action::SlowDown,
+            Action::SpeedUp,
+            Action::MoveSelectionUp,
+            Action::MoveSelectionDown,
+            Action::MoveSelectionLeft,
+            Action::MoveSelectionRight,
+            Action::Confirm,
+            Action::Cancel,
+        ];
+        result.extend(TrapKind::ALL.into_iter().map(Action::BuildTrap));
+        result.extend([
+            Action::UpgradeTrap,
+            Action::SellTrap,
+            Action::Undo,
+            Action::Redo,
+            Action::ZoomIn,
+            Action::ZoomOut,
+            Action::ResetCamera,
+            Action::ToggleDebugPanels,
+        ]);
+        result
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Action::Pause => "Pause".to_string(),
+            Action::SlowDown => "Slow down".to_string(),
+            Action::SpeedUp => "Speed up".to_string(),
+            Action::MoveSelectionUp => "Move selection up".to_string(),
+            Action::MoveSelectionDown => "Move selection down".to_string(),
+            Action::MoveSelectionLeft => "Move selection left".to_string(),
+            Action::MoveSelectionRight => "Move selection right".to_string(),
+            Action::Confirm => "Build on the selected tile".to_string(),
+            Action::Cancel => "Cancel building or open the menu".to_string(),
+            Action::BuildTrap(trap_kind) => format!("Pick {} trap", trap_kind.name()),
+            Action::UpgradeTrap => "Upgrade the selected trap".to_string(),
+            Action::SellTrap => "Sell the selected trap".to_string(),
+            Action::Undo => "Undo".to_string(),
+            Action::Redo => "Redo".to_string(),
+            Action::ZoomIn => "Zoom in".to_string(),
+            Action::ZoomOut => "Zoom out".to_string(),
+            Action::ResetCamera => "Show the whole map".to_string(),
+            Action::ToggleDebugPanels => "Toggle debug panels".to_string(),
+        }
+    }
+
+    pub fn default_binding(&self) -> KeyBinding {
+        match self {
+            Action::Pause => KeyBinding::new(KeyCode::P),
+            Action::SlowDown => KeyBinding::new(KeyCode::Minus),
+            Action::SpeedUp => KeyBinding::new(KeyCode::Equals),
+            Action::MoveSelectionUp => KeyBinding::new(KeyCode::Up),
+            Action::MoveSelectionDown => KeyBinding::new(KeyCode::Down),
+            Action::MoveSelectionLeft => KeyBinding::new(KeyCode::Left),
+            Action::MoveSelectionRight => KeyBinding::new(KeyCode::Right),
+            Action::Confirm => KeyBinding::new(KeyCode::Return),
+            Action::Cancel => KeyBinding::new(KeyCode::Escape),
+            Action::BuildTrap(trap_kind) => {
+                let index = TrapKind::ALL
+                    .iter()
+                    .position(|other| other == trap_kind)
+                    .unwrap_or(0);
+                KeyBinding::new(BUILD_KEYS.get(index).copied().unwrap_or(KeyCode::Num0))
+            }
+            Action::UpgradeTrap => KeyBinding::new(KeyCode::U),
+            Action::SellTrap => KeyBinding::new(KeyCode::Delete),
+            Action::Undo => KeyBinding {
+                ctrl: true,
+                ..KeyBinding::new(KeyCode::Z)
+            },
+            Action::Redo => KeyBinding {
+                ctrl: true,
+                ..KeyBinding::new(KeyCode::Y)
+            },
+            Action::ZoomIn => KeyBinding::new(KeyCode::PageUp),
+            Action::ZoomOut => KeyBinding::new(KeyCode::PageDown),
+            Action::ResetCamera => KeyBinding::new(KeyCode::Home),
+            Action::ToggleDebugPanels => KeyBinding::new(KeyCode::F1),
+        }
+    }
+}
+
+/// Stored by the name of the key, as comfy's key codes cannot be serialized
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "KeyBindingDef", into = "KeyBindingDef")]
+pub struct KeyBinding {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyBindingDef {
+    key: String,
+    #[serde(default)]
+    ctrl: bool,
+    #[serde(default)]
+    shift: bool,
+}
+
+impl From<KeyBinding> for KeyBindingDef {
+    fn from(binding: KeyBinding) -> Self {
+        Self {
+            key: format!("{:?}", binding.key),
+            ctrl: binding.ctrl,
+            shift: binding.shift,
+        }
+    }
+}
+
+impl TryFrom<KeyBindingDef> for KeyBinding {
+    type Error = String;
+
+    fn try_from(definition: KeyBindingDef) -> Result<Self, Self::Error> {
+        let key = BINDABLE_KEYS
+            .into_iter()
+            .find(|key| format!("{:?}", key) == definition.key)
+            .ok_or_else(|| format!("Unknown key {}", definition.key))?;
+        Ok(Self {
+            key,
+            ctrl: definition.ctrl,
+            shift: definition.shift,
+        })
+    }
+}
+
+impl KeyBinding {
+    pub fn new(key: KeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            shift: false,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+        if self.ctrl {
+            name.push_str("Ctrl+");
+        }
+        if self.shift {
+            name.push_str("Shift+");
+        }
+        let key = match self.key {
+            KeyCode::Minus => "-".to_string(),
+            KeyCode::Equals => "=".to_string(),
+            key => format!("{:?}", key).trim_start_matches("Num").to_string(),
+        };
+        name.push_str(&key);
+        name
+    }
+
+    fn is_pressed(&self) -> bool {
+        let is_ctrl_down = is_key_down(KeyCode::LCtrl) || is_key_down(KeyCode::RCtrl);
+        let is_shift_down = is_key_down(KeyCode::LShift) || is_key_down(KeyCode::RShift);
+        is_key_pressed(self.key) && self.ctrl == is_ctrl_down && self.shift == is_shift_down
+    }
+}
+
+/// Actions bound to keys, persisted between sessions
+#[derive(Serialize, Deserialize)]
+pub struct Keybindings {
+    #[serde(with = "map_as_pairs")]
+    bindings: HashMap<Action, KeyBinding>,
+    #[serde(skip)]
+    pub show_settings: bool,
+    /// Action waiting for a key to be pressed in the settings
+    #[serde(skip)]
+    rebinding: Option<Action>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self {
+            bindings: Action::all()
+                .into_iter()
+                .map(|action| (action, action.default_binding()))
+                .collect(),
+            show_settings: false,
+            rebinding: None,
+        }
+    }
+}
+
+impl Keybindings {
+    pub fn load() -> Result<Self> {
+        let mut keybindings: Self = storage()?.read_json(KEYBINDINGS_KEY)?.unwrap_or_default();
+        // Actions added since the bindings were saved get their defaults
+        for action in Action::all() {
+            keybindings
+                .bindings
+                .entry(action)
+                .or_insert_with(|| action.default_binding());
+        }
+        Ok(keybindings)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        storage()?.write_json(KEYBINDINGS_KEY, self)
+    }
+
+    pub fn binding(&self, action: Action) -> KeyBinding {
+        self.bindings
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_binding())
+    }
+
+    /// Short hint for labels, e.g. `Pause (P)`
+    pub fn hint(&self, action: Action) -> String {
+        self.binding(action).name()
+    }
+
+    /// Keys typed into a text field are not actions
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.rebinding.is_none()
+            && !egui().wants_keyboard_input()
+            && self.binding(action).is_pressed()
+    }
+
+    fn conflicts(&self, action: Action) -> Vec<Action> {
+        let binding = self.binding(action);
+        Action::all()
+            .into_iter()
+            .filter(|other| *other != action && self.binding(*other) == binding)
+            .collect()
+    }
+}
+
+impl GameState {
+    /// Label followed by the key of the action, touch devices have no keys to hint at
+    pub fn with_key_hint(&self, text: &str, action: Action) -> String {
+        match self.touch.is_touch_device {
+            true => text.to_string(),
+            false => format!("{} ({})", text, self.keybindings.hint(action)),
+        }
+    }
+
+    /// Moves the selected tile with the keyboard, starting in the middle of the map
+    pub fn selection_inputs(&mut self) {
+        let movements = [
+            (Action::MoveSelectionUp, IVec2::new(0, 1)),
+            (Action::MoveSelectionDown, IVec2::new(0, -1)),
+            (Action::MoveSelectionLeft, IVec2::new(-1, 0)),
+            (Action::MoveSelectionRight, IVec2::new(1, 0)),
+        ];
+        for (action, movement) in movements {
+            if !self.keybindings.is_pressed(action) {
+                continue;
+            }
+            let selected_tile = self.selected_tile.unwrap_or(TileMapPos::new(
+                self.tile_map.columns / 2,
+                self.tile_map.rows / 2,
+            ));
+            let moved = IVec2::new(selected_tile.x as i32, selected_tile.y as i32) + movement;
+            if moved.x < 0 || moved.y < 0 {
+                continue;
+            }
+            let moved = TileMapPos::new(moved.x as u32, moved.y as u32);
+            if self.tile_map.in_bounds(moved) {
+                self.selected_tile = Some(moved);
+                self.scroll_camera_to(moved);
+            }
+        }
+    }
+
+    pub fn keybindings_ui(&mut self) {
+        if !self.keybindings.show_settings {
+            return;
+        }
+        let mut is_open = true;
+        let mut changed = false;
+        let keybindings = &mut self.keybindings;
+        if let Some(action) = keybindings.rebinding {
+            let is_ctrl_down = is_key_down(KeyCode::LCtrl) || is_key_down(KeyCode::RCtrl);
+            let is_shift_down = is_key_down(KeyCode::LShift) || is_key_down(KeyCode::RShift);
+            if let Some(key) = BINDABLE_KEYS.into_iter().find(|key| is_key_pressed(*key)) {
+                keybindings.bindings.insert(
+                    action,
+                    KeyBinding {
+                        key,
+                        ctrl: is_ctrl_down,
+                        shift: is_shift_down,
+                    },
+                );
+                keybindings.rebinding = None;
+                changed = true;
+            }
+        }
+        egui::Window::new("Keybindings")
+            .anchor(Align2::CENTER_CENTER, [0., 0.])
+            .collapsible(false)
+            .open(&mut is_open)
+            .show(egui(), |ui| {
+                egui::Grid::new("Keybindings").striped(true).show(ui, |ui| {
+                    for action in Action::all() {
+                        ui.label(action.name());
+                        let text = match keybindings.rebinding == Some(action) {
+                            true => "Press a key...".to_string(),
+                            false => keybindings.hint(action),
+                        };
+                        if ui.button(text).clicked() {
+                            keybindings.rebinding = Some(action);
+                        }
+                        let conflicts = keybindings.conflicts(action);
+                        if !conflicts.is_empty() {
+                            ui.colored_label(
+                                RED.egui(),
+                                format!(
+                                    "Also used by {}",
+                                    conflicts.iter().map(|other| other.name()).join(", ")
+                                ),
+                            );
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if keybindings.rebinding.is_some() && ui.button("Cancel").clicked() {
+                        keybindings.rebinding = None;
+                    }
+                    if ui.button("Reset to defaults").clicked() {
+                        keybindings.bindings = Keybindings::default().bindings;
+                        changed = true;
+                    }
+                });
+            });
+        if !is_open {
+            keybindings.show_settings = false;
+            keybindings.rebinding = None;
+        }
+        if changed {
+            if let Err(err) = self.keybindings.save() {
+                self.toasts
+                    .error(format!("Failed to save keybindings: {}", err));
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use comfy::*;
+
+use crate::*;
+
+/// Screens narrower than this, in egui points, get the compact layout meant for phones
+const COMPACT_WIDTH: f32 = 800.;
+/// Set before reloading the page, so the saved run is continued afterwards
+const RESUME_AFTER_RELOAD_KEY: &str = "resume_after_reload";
+/// Relative change of the viewport size which is worth reloading the page for
+#[cfg(target_arch = "wasm32")]
+const VIEWPORT_CHANGE_TOLERANCE: f32 = 0.1;
+
+pub fn is_compact_layout() -> bool {
+    egui().screen_rect().width() < COMPACT_WIDTH
+}
+
+/// The browser viewport on the web, native windows keep a fixed resolution
+pub fn initial_resolution() -> ResolutionConfig {
+    #[cfg(target_arch = "wasm32")]
+    if let Some(viewport) = viewport_size() {
+        return ResolutionConfig::Physical(viewport.x as u32, viewport.y as u32);
+    }
+    ResolutionConfig::Physical(1920, 1080)
+}
+
+/// Size of the browser viewport in physical pixels
+#[cfg(target_arch = "wasm32")]
+fn viewport_size() -> Option<Vec2> {
+    let window = web_sys::window()?;
+    let pixel_ratio = window.device_pixel_ratio();
+    let width = window.inner_width().ok()?.as_f64()? * pixel_ratio;
+    let height = window.inner_height().ok()?.as_f64()? * pixel_ratio;
+    Some(Vec2::new(width as f32, height as f32))
+}
+
+impl GameState {
+    /// Winit cannot resize the canvas once the game runs, so rotating the phone
+    /// or resizing the browser saves the run and reloads the page to fit again
+    pub fn follow_viewport_size(&mut self) {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let Some(viewport) = viewport_size() else {
+                return;
+            };
+            let screen = Vec2::new(screen_width(), screen_height());
+            if screen.min_element() <= 0. {
+                return;
+            }
+            let change = ((viewport - screen).abs() / screen).max_element();
+            if change < VIEWPORT_CHANGE_TOLERANCE {
+                return;
+            }
+            // Only runs in progress are resumed, not the menus, finished runs or the editor
+            let can_reload = !self.flow.is_run_started || self.editor.is_some() || {
+                self.autosave();
+                storage()
+                    .and_then(|storage| storage.write(RESUME_AFTER_RELOAD_KEY, "true"))
+                    .is_ok()
+            };
+            if can_reload {
+                if let Some(window) = web_sys::window() {
+                    let _ = window.location().reload();
+                }
+            }
+        }
+    }
+
+    /// Continues the run saved by `follow_viewport_size` before the page was reloaded
+    pub fn resume_after_reload(&mut self) {
+        let should_resume = storage()
+            .and_then(|storage| {
+                let value = storage.read(RESUME_AFTER_RELOAD_KEY)?;
+                storage.remove(RESUME_AFTER_RELOAD_KEY)?;
+                Ok(value.is_some())
+            })
+            .unwrap_or(false);
+        if !should_resume {
+            return;
+        }
+        if let Err(err) = self.load_run() {
+            self.toasts
+                .error(format!("Failed to resume the run: {}", err));
+        }
+    }
+}
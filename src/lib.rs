@@ -1,15 +1,64 @@
+mod assets;
+mod audio;
+mod build_mode;
+mod camera;
+mod dps_meter;
+mod editor;
+mod effects;
 mod enemy;
 mod enemy_spawner;
+mod event_log;
+mod events;
+mod floating_text;
+mod flow;
+mod game_speed;
+mod high_scores;
 mod hit_points;
+mod hud;
+mod keybindings;
+mod layout;
+mod pathfinding;
 mod player;
+mod rng;
+mod save;
+mod sprites;
 mod state;
+mod statistics;
+mod storage;
 pub mod tile_map;
+mod toasts;
+mod touch;
 mod trap;
+mod undo;
 
+pub use assets::*;
+pub use audio::*;
+pub use build_mode::*;
+pub use camera::*;
+pub use editor::*;
+pub use effects::*;
 pub use enemy::*;
 pub use enemy_spawner::*;
+pub use event_log::*;
+pub use events::*;
+pub use floating_text::*;
+pub use flow::*;
+pub use game_speed::*;
+pub use high_scores::*;
 pub use hit_points::*;
+pub use hud::*;
+pub use keybindings::*;
+pub use layout::*;
+pub use pathfinding::*;
 pub use player::*;
+pub use rng::*;
+pub use save::*;
+pub use sprites::*;
 pub use state::*;
-pub use tile_map::TileMapPos;
+pub use statistics::*;
+pub use storage::*;
+pub use tile_map::{Terrain, TileMap, TileMapPos};
+pub use toasts::*;
+pub use touch::*;
 pub use trap::*;
+pub use undo::*;
@@ -3,38 +3,79 @@ use trapify::*;
 
 simple_game!("Trapify", GameState, config, setup, update);
 
+// `ComfyGame` is generated by `simple_game!` and only dropped once the window is closed
+impl Drop for ComfyGame {
+    fn drop(&mut self) {
+        self.state.autosave();
+        self.state.write_unfinished_event_log();
+    }
+}
+
 fn config(config: GameConfig) -> GameConfig {
     GameConfig {
         // This overrides the default ResolutionConfig::Logical(1106, 526) for WASM targets
-        resolution: ResolutionConfig::Physical(1920, 1080),
+        resolution: initial_resolution(),
         ..config
     }
 }
 
-fn setup(_state: &mut GameState, _c: &mut EngineContext) {}
+fn setup(state: &mut GameState, c: &mut EngineContext) {
+    let failures = load_sprites(c);
+    for failure in failures.into_iter().chain(state.audio.load_sounds()) {
+        state.toasts.error(failure);
+    }
+    state.load_map_from_args();
+    state.load_event_log_path_from_args();
+    state.resume_after_reload();
+}
 
-fn update(state: &mut GameState, _c: &mut EngineContext) {
+fn update(state: &mut GameState, c: &mut EngineContext) {
     #[cfg(target_arch = "wasm32")]
     let start_time = web_sys::window().unwrap().performance().unwrap().now();
 
-    state.passage_of_time();
-
     state.draw();
-    state.check_inputs();
+    state.flow_inputs();
 
-    state.deal_damage_over_time_to_enemies();
-    state.activate_traps();
-    state.cleanup_dead_enemies();
+    // Menus are shown over the run, which only moves on while playing
+    if state.flow.screen == Screen::Playing {
+        state.check_inputs();
+    }
+    // The game speed changes how many times the simulation runs per frame, not the rendering
+    let steps = match state.flow.screen {
+        Screen::Playing => state.game_speed.steps_per_frame(),
+        _ => 0,
+    };
+    for _ in 0..steps {
+        state.passage_of_time();
+
+        state.deal_damage_over_time_to_enemies();
+        state.activate_traps();
+        state.cleanup_dead_enemies();
 
-    state.spawn_enemies();
-    state.move_enemies_and_deal_damage_to_player();
+        state.spawn_enemies();
+        state.announce_waves();
+        state.move_enemies_and_deal_damage_to_player();
 
-    state.check_dead_player();
-    state.check_game_over();
+        state.check_dead_player();
+        state.check_victory();
 
-    state.apply_gold_interest();
+        state.apply_gold_interest();
+        state.collect_statistics();
+    }
+    state.update_flow();
+
+    state.update_floating_texts();
+    state.update_effects();
+    state.update_audio();
+    state.autosave_periodically();
+    state.follow_viewport_size();
 
     state.ui();
+    state.dispatch_events();
+    state.record_finished_run();
+    if state.flow.should_quit {
+        *c.quit_flag = true;
+    }
 
     // Hack to have lower FPS in wasm -- wait until 16 ms have passed since the beginning of update
     #[cfg(target_arch = "wasm32")]
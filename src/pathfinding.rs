@@ -0,0 +1,326 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use comfy::*;
+
+use crate::*;
+
+/// Extra cost for switching rows, keeps `Straight` enemies in their lane unless blocked.
+const LANE_CHANGE_COST: f32 = 5.;
+pub const BLOCKED_PATH_WARNING: &str =
+    "Enemies need a way to the exit, a wall cannot block every path";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MovementLayer {
+    Ground,
+    /// Flies over walls and ground-only traps
+    Air,
+}
+
+impl MovementLayer {
+    pub const ALL: [MovementLayer; 2] = [MovementLayer::Ground, MovementLayer::Air];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EnemyMovement {
+    /// Keeps to its row, only changing lanes to get around walls
+    Straight,
+    /// Prefers tiles where traps deal less damage
+    AvoidDamage,
+    /// Takes the shortest walkable path to the exit
+    ShortestPath,
+}
+
+impl EnemyMovement {
+    pub const ALL: [EnemyMovement; 3] = [
+        EnemyMovement::Straight,
+        EnemyMovement::AvoidDamage,
+        EnemyMovement::ShortestPath,
+    ];
+
+    fn step_cost(
+        &self,
+        from: TileMapPos,
+        to: TileMapPos,
+        layer: MovementLayer,
+        tile_map: &TileMap,
+        trap_tiles: &HashMap<TileMapPos, TrapTile>,
+    ) -> f32 {
+        // Costs are in time spent, so slow terrain is more expensive to cross
+        let time_on_tile = 1. / terrain_speed_multiplier(to, layer, tile_map);
+        match self {
+            EnemyMovement::Straight => match from.y == to.y {
+                true => time_on_tile,
+                false => time_on_tile + LANE_CHANGE_COST,
+            },
+            EnemyMovement::AvoidDamage => {
+                time_on_tile * (1. + danger(to, layer, tile_map, trap_tiles))
+            }
+            EnemyMovement::ShortestPath => time_on_tile,
+        }
+    }
+}
+
+pub enum Step {
+    Move(TileMapPos),
+    Exit,
+}
+
+/// Cost of reaching the exit from every walkable tile, for a single movement type and layer.
+pub struct DistanceField {
+    next_tiles: HashMap<TileMapPos, TileMapPos>,
+}
+
+impl DistanceField {
+    pub fn new(
+        movement: EnemyMovement,
+        layer: MovementLayer,
+        tile_map: &TileMap,
+        trap_tiles: &HashMap<TileMapPos, TrapTile>,
+    ) -> Self {
+        Self::with_wall(movement, layer, tile_map, trap_tiles, None)
+    }
+
+    /// Dijkstra run backwards from the exits, walls are never walked through. As if
+    /// `extra_wall` was built as well, to check a wall before building it.
+    fn with_wall(
+        movement: EnemyMovement,
+        layer: MovementLayer,
+        tile_map: &TileMap,
+        trap_tiles: &HashMap<TileMapPos, TrapTile>,
+        extra_wall: Option<TileMapPos>,
+    ) -> Self {
+        let is_open = |tile_map_pos: TileMapPos| {
+            is_walkable(tile_map_pos, layer, trap_tiles)
+                && (layer == MovementLayer::Air || extra_wall != Some(tile_map_pos))
+        };
+        let mut distances = HashMap::new();
+        let mut next_tiles = HashMap::new();
+        let mut heap = BinaryHeap::new();
+
+        for exit in tile_map.exits.iter() {
+            distances.insert(*exit, 0.);
+            heap.push(Reverse((OrderedFloat(0.), exit.x, exit.y)));
+        }
+
+        while let Some(Reverse((OrderedFloat(distance), x, y))) = heap.pop() {
+            let to = TileMapPos::new(x, y);
+            if distances.get(&to).is_some_and(|best| *best < distance) {
+                continue;
+            }
+            for from in tile_map.neighbors(to) {
+                let candidate =
+                    distance + movement.step_cost(from, to, layer, tile_map, trap_tiles);
+                if distances.get(&from).is_none_or(|best| candidate < *best) {
+                    distances.insert(from, candidate);
+                    next_tiles.insert(from, to);
+                    // Enemies caught on a tile when a wall was built there can still step off
+                    // it, but no path leads through it
+                    if is_open(from) {
+                        heap.push(Reverse((OrderedFloat(candidate), from.x, from.y)));
+                    }
+                }
+            }
+        }
+
+        Self { next_tiles }
+    }
+
+    pub fn next_step(&self, from: TileMapPos) -> Step {
+        match self.next_tiles.get(&from) {
+            Some(next_tile) => Step::Move(*next_tile),
+            // Map validation and the check before building walls make sure every
+            // spawn can reach an exit, so only exits are left without a next tile
+            None => Step::Exit,
+        }
+    }
+
+    fn reaches_exit(&self, from: TileMapPos, tile_map: &TileMap) -> bool {
+        tile_map.exits.contains(&from) || self.next_tiles.contains_key(&from)
+    }
+}
+
+/// Distance fields of every movement type and layer, rebuilt only once the traps
+/// or the map changed
+#[derive(Default)]
+pub struct DistanceFields {
+    fields: Option<HashMap<(EnemyMovement, MovementLayer), DistanceField>>,
+}
+
+impl DistanceFields {
+    pub fn invalidate(&mut self) {
+        self.fields = None;
+    }
+
+    pub fn get(
+        &mut self,
+        tile_map: &TileMap,
+        trap_tiles: &HashMap<TileMapPos, TrapTile>,
+    ) -> &HashMap<(EnemyMovement, MovementLayer), DistanceField> {
+        self.fields.get_or_insert_with(|| {
+            EnemyMovement::ALL
+                .into_iter()
+                .cartesian_product(MovementLayer::ALL)
+                .map(|(movement, layer)| {
+                    let distance_field = DistanceField::new(movement, layer, tile_map, trap_tiles);
+                    ((movement, layer), distance_field)
+                })
+                .collect()
+        })
+    }
+}
+
+fn is_walkable(
+    tile_map_pos: TileMapPos,
+    layer: MovementLayer,
+    trap_tiles: &HashMap<TileMapPos, TrapTile>,
+) -> bool {
+    layer == MovementLayer::Air
+        || !matches!(
+            trap_tiles.get(&tile_map_pos),
+            Some(TrapTile::Built { trap, .. }) if trap.blocks_movement()
+        )
+}
+
+/// Terrain only affects enemies walking on it
+pub fn terrain_speed_multiplier(
+    tile_map_pos: TileMapPos,
+    layer: MovementLayer,
+    tile_map: &TileMap,
+) -> f32 {
+    match layer {
+        MovementLayer::Ground => tile_map.terrain_at(tile_map_pos).speed_multiplier(),
+        MovementLayer::Air => 1.,
+    }
+}
+
+/// Expected damage per second an enemy of the given layer takes on the given tile
+fn danger(
+    tile_map_pos: TileMapPos,
+    layer: MovementLayer,
+    tile_map: &TileMap,
+    trap_tiles: &HashMap<TileMapPos, TrapTile>,
+) -> f32 {
+    match trap_tiles.get(&tile_map_pos) {
+        Some(TrapTile::Built { trap, .. }) if trap.target_layers().contains(&layer) => {
+            trap.damage_per_second()
+                * tile_map
+                    .terrain_at(tile_map_pos)
+                    .trap_multiplier(trap.kind())
+        }
+        _ => 0.,
+    }
+}
+
+impl GameState {
+    /// Whether every spawn and every walking enemy could still reach an exit with a
+    /// wall on the tile
+    pub fn keeps_paths_open(&self, wall: TileMapPos) -> bool {
+        let distance_field = DistanceField::with_wall(
+            EnemyMovement::ShortestPath,
+            MovementLayer::Ground,
+            &self.tile_map,
+            &self.trap_tiles,
+            Some(wall),
+        );
+        let enemy_tiles = self
+            .enemies
+            .iter()
+            .filter(|enemy| enemy.archetype.layer() == MovementLayer::Ground)
+            .map(|enemy| enemy.next_tile);
+        self.tile_map
+            .spawns
+            .iter()
+            .copied()
+            .chain(enemy_tiles)
+            .all(|tile_map_pos| distance_field.reaches_exit(tile_map_pos, &self.tile_map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile_map() -> TileMap {
+        TileMap::from_definition(&tile_map::MapDefinition {
+            name: "Test".to_string(),
+            tiles: vec!["S...E".to_string(), ".....".to_string()],
+            waves: vec![],
+        })
+        .unwrap()
+    }
+
+    fn wall_at(tile_map_pos: TileMapPos) -> HashMap<TileMapPos, TrapTile> {
+        let wall = TrapTile::Built {
+            trap: TrapBuilder::default_wall().into_trap(),
+            upgrades: 0,
+            gold_spent: 0.,
+            hits: 0,
+        };
+        HashMap::from_iter([(tile_map_pos, wall)])
+    }
+
+    fn moves_to(step: Step, expected: TileMapPos) -> bool {
+        matches!(step, Step::Move(next) if next == expected)
+    }
+
+    #[test]
+    fn walking_enemies_go_around_walls() {
+        let tile_map = tile_map();
+        let (before_wall, wall) = (TileMapPos::new(1, 1), TileMapPos::new(2, 1));
+        let open = DistanceField::new(
+            EnemyMovement::ShortestPath,
+            MovementLayer::Ground,
+            &tile_map,
+            &HashMap::new(),
+        );
+        assert!(moves_to(open.next_step(before_wall), wall));
+
+        let trap_tiles = wall_at(wall);
+        let walled = DistanceField::new(
+            EnemyMovement::ShortestPath,
+            MovementLayer::Ground,
+            &tile_map,
+            &trap_tiles,
+        );
+        assert!(moves_to(
+            walled.next_step(before_wall),
+            TileMapPos::new(1, 0)
+        ));
+        // An enemy caught on the wall's tile can still leave it
+        assert!(matches!(walled.next_step(wall), Step::Move(_)));
+        assert!(matches!(
+            walled.next_step(TileMapPos::new(4, 1)),
+            Step::Exit
+        ));
+    }
+
+    #[test]
+    fn flying_enemies_ignore_walls() {
+        let tile_map = tile_map();
+        let trap_tiles = wall_at(TileMapPos::new(2, 1));
+        let field = DistanceField::new(
+            EnemyMovement::ShortestPath,
+            MovementLayer::Air,
+            &tile_map,
+            &trap_tiles,
+        );
+        assert!(moves_to(
+            field.next_step(TileMapPos::new(1, 1)),
+            TileMapPos::new(2, 1)
+        ));
+    }
+
+    #[test]
+    fn walls_cutting_off_every_path_are_detected() {
+        let tile_map = tile_map();
+        let field = DistanceField::with_wall(
+            EnemyMovement::ShortestPath,
+            MovementLayer::Ground,
+            &tile_map,
+            &wall_at(TileMapPos::new(3, 1)),
+            Some(TileMapPos::new(3, 0)),
+        );
+        assert!(!field.reaches_exit(TileMapPos::new(0, 1), &tile_map));
+        assert!(field.reaches_exit(TileMapPos::new(4, 0), &tile_map));
+    }
+}
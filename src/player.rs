@@ -1,14 +1,18 @@
 use comfy::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{GameState, HitPoints};
+use crate::{timer_serde, GameEvent, GameState, GoldChangeReason, HitPoints};
 
+#[derive(Serialize, Deserialize)]
 pub struct Player {
     pub hp: HitPoints,
     pub gold: Gold,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Gold {
     pub value: f32,
+    #[serde(with = "timer_serde")]
     pub interest_cooldown: Timer,
     pub interest_size: f32,
     pub max_interest_gainable: f32,
@@ -21,11 +25,12 @@ impl Gold {
 }
 
 impl Player {
-    pub fn debug_ui(&mut self, ui: &mut egui::Ui) {
+    /// Cheats are only available in sandbox mode
+    pub fn debug_ui(&mut self, ui: &mut egui::Ui, allow_cheats: bool) {
         ui.heading("Player");
         ui.separator();
         ui.horizontal(|ui| {
-            if ui.button("Reset HP").clicked() {
+            if allow_cheats && ui.button("Reset HP").clicked() {
                 self.hp.reset();
             }
             ui.label("HP: ");
@@ -36,7 +41,7 @@ impl Player {
             );
         });
         ui.horizontal(|ui| {
-            if ui.button("Top up 10").clicked() {
+            if allow_cheats && ui.button("Top up 10").clicked() {
                 self.gold.value += 10.;
             }
             ui.label(format!("Gold: {}", self.gold.value));
@@ -68,19 +73,38 @@ impl Player {
 
 impl GameState {
     pub fn check_dead_player(&mut self) {
-        if self.player.hp.is_dead() {
+        if self.player.hp.is_dead() && !self.is_game_over {
             self.is_game_over = true;
             self.is_paused = true;
+            self.event_bus.emit(GameEvent::GameOver);
         }
     }
 
+    /// Maps with waves are won once every wave spawned and no enemy is left
+    pub fn check_victory(&mut self) {
+        if self.is_game_over
+            || !self.enemies.is_empty()
+            || !self.enemy_spawner.all_waves_spawned(&self.tile_map.waves)
+        {
+            return;
+        }
+        self.is_game_over = true;
+        self.is_paused = true;
+        self.event_bus.emit(GameEvent::Victory);
+    }
+
     pub fn apply_gold_interest(&mut self) {
         if self.is_paused {
             return;
         }
         self.player.gold.interest_cooldown.tick_secs(self.delta);
         if self.player.gold.interest_cooldown.just_finished() {
-            self.player.gold.value += self.player.gold.interest_to_gain()
+            let interest = self.player.gold.interest_to_gain();
+            self.player.gold.value += interest;
+            self.event_bus.emit(GameEvent::GoldChanged {
+                amount: interest,
+                reason: GoldChangeReason::Interest,
+            });
         }
     }
 }
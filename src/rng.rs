@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// Random generator of a run, saved with it so a loaded run draws the same
+/// numbers it would have drawn without the save. SplitMix64, enough for gameplay
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunRng {
+    state: u64,
+}
+
+impl RunRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly picks one element, `None` for an empty slice
+    pub fn choose<T: Copy>(&mut self, items: &[T]) -> Option<T> {
+        match items.len() {
+            0 => None,
+            len => Some(items[(self.next_u64() % len as u64) as usize]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_draws_the_same_numbers() {
+        let (mut first, mut second) = (RunRng::new(7), RunRng::new(7));
+        let draws = (0..10).map(|_| first.next_u64()).collect::<Vec<_>>();
+        assert_eq!(
+            draws,
+            (0..10).map(|_| second.next_u64()).collect::<Vec<_>>()
+        );
+        assert_ne!(RunRng::new(8).next_u64(), draws[0]);
+    }
+
+    #[test]
+    fn choose_picks_from_the_slice() {
+        let mut rng = RunRng::new(1);
+        assert_eq!(rng.choose::<u32>(&[]), None);
+        for _ in 0..100 {
+            assert!(rng
+                .choose(&[3, 5, 8])
+                .is_some_and(|item| [3, 5, 8].contains(&item)));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use comfy::*;
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// Bump whenever the serialized shape of `GameState` changes
+pub const SAVE_VERSION: u32 = 7;
+/// Browsers never give a chance to save on close, so runs are also saved periodically
+const AUTOSAVE_INTERVAL_SECS: f32 = 10.;
+
+#[derive(Serialize)]
+struct SaveFileRef<'a> {
+    version: u32,
+    state: &'a GameState,
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct SaveFile {
+    state: GameState,
+}
+
+impl GameState {
+    pub fn to_save_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&SaveFileRef {
+            version: SAVE_VERSION,
+            state: self,
+        })?)
+    }
+
+    pub fn from_save_json(json: &str) -> Result<GameState> {
+        let header: SaveHeader = serde_json::from_str(json)?;
+        if header.version != SAVE_VERSION {
+            bail!(
+                "Save file version {} is not supported, expected {}",
+                header.version,
+                SAVE_VERSION
+            );
+        }
+        let save_file: SaveFile = serde_json::from_str(json)?;
+        Ok(save_file.state)
+    }
+
+    pub fn save_run(&self) -> Result<()> {
+        storage()?.write(SAVE_KEY, &self.to_save_json()?)
+    }
+
+    pub fn load_run(&mut self) -> Result<()> {
+        let Some(json) = storage()?.read(SAVE_KEY)? else {
+            bail!("There is no saved run");
+        };
+        let loaded = Self::from_save_json(&json)?;
+        self.write_unfinished_event_log();
+        *self = Self {
+            map_path: std::mem::take(&mut self.map_path),
+            high_scores: std::mem::take(&mut self.high_scores),
+            show_dps_overlay: self.show_dps_overlay,
+            event_log_path: self.event_log_path.take(),
+            audio: std::mem::take(&mut self.audio),
+            hud: std::mem::take(&mut self.hud),
+            toasts: std::mem::take(&mut self.toasts),
+            game_speed: self.game_speed,
+            keybindings: std::mem::take(&mut self.keybindings),
+            touch: std::mem::take(&mut self.touch),
+            camera: std::mem::take(&mut self.camera),
+            flow: std::mem::take(&mut self.flow),
+            event_bus: self.event_bus.for_next_run(),
+            last_autosave_secs: loaded.run_length_seconds,
+            ..loaded
+        };
+        self.start_playing();
+        Ok(())
+    }
+
+    /// Saves the run when quitting, finished runs are not worth continuing and
+    /// the one behind the main menu would overwrite the run to continue
+    pub fn autosave(&mut self) {
+        if !self.is_game_over && self.editor.is_none() && self.flow.is_run_started {
+            if let Err(err) = self.save_run() {
+                self.toasts.error(format!("Failed to autosave: {}", err));
+            }
+        }
+    }
+
+    pub fn autosave_periodically(&mut self) {
+        if self.is_paused {
+            return;
+        }
+        if self.run_length_seconds - self.last_autosave_secs >= AUTOSAVE_INTERVAL_SECS {
+            self.last_autosave_secs = self.run_length_seconds;
+            self.autosave();
+        }
+    }
+
+    pub fn save_debug_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Save run").clicked() {
+                match self.save_run() {
+                    Ok(()) => self.toasts.info("Run saved"),
+                    Err(err) => self.toasts.error(format!("Failed to save: {}", err)),
+                }
+            }
+            if ui.button("Load run").clicked() {
+                match self.load_run() {
+                    Ok(()) => self.toasts.info("Run loaded"),
+                    Err(err) => self.toasts.error(format!("Failed to load: {}", err)),
+                }
+            }
+        });
+    }
+}
+
+/// Comfy's `Timer` does not implement serde traits, store its progress instead
+pub mod timer_serde {
+    use comfy::{Duration, Timer};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct TimerDef {
+        duration_secs: f32,
+        elapsed_secs: f32,
+        repeating: bool,
+    }
+
+    pub fn serialize<S: Serializer>(timer: &Timer, serializer: S) -> Result<S::Ok, S::Error> {
+        TimerDef {
+            duration_secs: timer.duration().as_secs_f32(),
+            elapsed_secs: timer.elapsed_secs(),
+            repeating: timer.repeating(),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Timer, D::Error> {
+        let timer_def = TimerDef::deserialize(deserializer)?;
+        let mut timer = Timer::from_seconds(timer_def.duration_secs, timer_def.repeating);
+        timer.set_elapsed(Duration::from_secs_f32(timer_def.elapsed_secs));
+        Ok(timer)
+    }
+}
+
+pub mod vec2_serde {
+    use comfy::Vec2;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(vec: &Vec2, serializer: S) -> Result<S::Ok, S::Error> {
+        [vec.x, vec.y].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec2, D::Error> {
+        let [x, y] = <[f32; 2]>::deserialize(deserializer)?;
+        Ok(Vec2::new(x, y))
+    }
+}
+
+/// JSON only supports string keys, so maps keyed by structs are stored as a list of pairs
+pub mod map_as_pairs {
+    use std::{collections::HashMap, hash::Hash};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_mid_run() -> GameState {
+        let mut state = GameState::default();
+        let tile = TileMapPos::new(2, 3);
+        let source = DamageSource {
+            tile,
+            trap_kind: TrapKind::DamageOverTime,
+        };
+        let mut trap = TrapBuilder::default_damage_over_time().into_trap();
+        if let Trap::DamageOverTime { cooldown, .. } = &mut trap {
+            cooldown.tick_secs(0.25);
+        }
+        state.trap_tiles.insert(
+            tile,
+            TrapTile::Built {
+                trap,
+                upgrades: 1,
+                gold_spent: 35.,
+                hits: 4,
+            },
+        );
+        state.enemies.push(Enemy {
+            archetype: EnemyArchetype::Flyer,
+            hp: HitPoints::new_full(12.),
+            speed: 1.,
+            damage: 3.,
+            position: Vec2::new(1.5, -2.25),
+            next_tile: tile,
+            size: 0.5,
+            damage_over_time_effects: vec![DamageOverTimeEffect {
+                timer: Timer::from_seconds(2., false),
+                damage_per_second: 2.,
+                source,
+            }],
+            gold_for_kill: 1.,
+            slow_effects: vec![],
+            last_hit_by: Some(source),
+        });
+        state.statistics.on_event(
+            1.,
+            &GameEvent::DamageDealt {
+                source,
+                damage: 7.,
+                position: Vec2::ZERO,
+            },
+        );
+        state.player.gold.interest_cooldown.tick_secs(3.5);
+        state.run_length_seconds = 42.;
+        state.rng.next_u64();
+        state
+    }
+
+    #[test]
+    fn save_round_trip() {
+        let mut state = state_mid_run();
+        let mut loaded = GameState::from_save_json(&state.to_save_json().unwrap()).unwrap();
+
+        assert_eq!(loaded.run_length_seconds, 42.);
+        assert_eq!(loaded.seed, state.seed);
+        assert_eq!(loaded.rng.next_u64(), state.rng.next_u64());
+        assert_eq!(loaded.player.gold.interest_cooldown.elapsed_secs(), 3.5);
+
+        let [enemy] = &loaded.enemies[..] else {
+            panic!("Expected a single enemy");
+        };
+        assert_eq!(enemy.position, Vec2::new(1.5, -2.25));
+        assert_eq!(
+            enemy.damage_over_time_effects[0]
+                .timer
+                .duration()
+                .as_secs_f32(),
+            2.
+        );
+        assert_eq!(enemy.last_hit_by, state.enemies[0].last_hit_by);
+
+        let Some(TrapTile::Built {
+            trap: Trap::DamageOverTime { cooldown, .. },
+            upgrades: 1,
+            hits: 4,
+            ..
+        }) = loaded.trap_tiles.get(&TileMapPos::new(2, 3))
+        else {
+            panic!("Expected the built trap");
+        };
+        assert_eq!(cooldown.elapsed_secs(), 0.25);
+
+        let source = DamageSource {
+            tile: TileMapPos::new(2, 3),
+            trap_kind: TrapKind::DamageOverTime,
+        };
+        assert_eq!(loaded.statistics.per_trap[&source].damage_dealt, 7.);
+        assert_eq!(
+            loaded.tile_map.to_definition().tiles,
+            state.tile_map.to_definition().tiles
+        );
+    }
+
+    #[test]
+    fn other_save_versions_are_rejected() {
+        let json = state_mid_run().to_save_json().unwrap();
+        let mut save_file: serde_json::Value = serde_json::from_str(&json).unwrap();
+        save_file["version"] = (SAVE_VERSION - 1).into();
+        let error = GameState::from_save_json(&save_file.to_string())
+            .err()
+            .expect("An older save was loaded");
+        assert!(error.to_string().contains("not supported"));
+    }
+}
@@ -0,0 +1,77 @@
+use comfy::*;
+
+use crate::*;
+
+const SPRITES_DIR: &str = "sprites";
+/// Frames per second of the walking animation of enemies
+pub const WALK_ANIMATION_FPS: f32 = 6.;
+/// How long the triggering animation of traps plays after their cooldown finished
+pub const TRIGGER_ANIMATION_SECS: f32 = 0.3;
+
+/// Registers every PNG under `assets/sprites` as a texture named by its path
+/// without the extension, e.g. `enemies/walker`. Sprites are horizontal strips
+/// of square frames, a single square image is a sprite with one frame.
+/// Returns why the files which could not be loaded were skipped.
+pub fn load_sprites(c: &EngineContext) -> Vec<String> {
+    let mut failures = Vec::new();
+    for (name, bytes) in embedded_assets(SPRITES_DIR, "png") {
+        // Comfy panics on images it cannot decode, so broken files fall back to colors
+        if let Err(err) = image::load_from_memory(bytes) {
+            failures.push(format!("Failed to load sprite {}: {}", name, err));
+            continue;
+        }
+        c.load_texture_from_bytes(&name, bytes);
+    }
+    failures
+}
+
+/// First frame of an embedded sprite, for showing it in the UI
+pub fn sprite_first_frame(name: &str) -> Option<image::RgbaImage> {
+    let (_, bytes) = embedded_assets(SPRITES_DIR, "png")
+        .into_iter()
+        .find(|(other, _)| other == name)?;
+    let image = image::load_from_memory(bytes).ok()?;
+    let frame_size = image.height();
+    Some(image.crop_imm(0, 0, frame_size, frame_size).to_rgba8())
+}
+
+pub fn sprite_frames(name: &str) -> Option<u32> {
+    match Assets::image_size(texture_id_safe(name)?) {
+        ImageSizeResult::Loaded(size) if size.y > 0 => Some((size.x / size.y).max(1)),
+        _ => None,
+    }
+}
+
+/// Draws one frame of a sprite, wrapping around its frame count.
+/// Returns false when the sprite is missing so the caller can draw a fallback.
+pub fn draw_sprite_frame(
+    name: &str,
+    position: Vec2,
+    size: Vec2,
+    tint: Color,
+    z_index: i32,
+    frame: u32,
+) -> bool {
+    let (Some(texture), Some(frames)) = (texture_id_safe(name), sprite_frames(name)) else {
+        return false;
+    };
+    let ImageSizeResult::Loaded(image_size) = Assets::image_size(texture) else {
+        return false;
+    };
+    let frame_size = image_size.y as i32;
+    draw_sprite_ex(
+        texture,
+        position,
+        tint,
+        z_index,
+        DrawTextureParams {
+            dest_size: Some(Size::world(size.x, size.y)),
+            source_rect: Some(IRect::new(
+                ivec2((frame % frames) as i32 * frame_size, 0),
+                ivec2(frame_size, frame_size),
+            )),
+            ..Default::default()
+        },
+    );
+    true
+}
@@ -1,20 +1,79 @@
 use crate::*;
-use comfy::{egui::Align2, *};
+use comfy::*;
+use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize)]
 pub struct GameState {
+    #[serde(skip)]
     pub delta: f32,
     pub enemies: Vec<Enemy>,
     pub enemy_spawner: EnemySpawner,
     pub player: Player,
     pub is_paused: bool,
     pub is_game_over: bool,
+    /// The finished run is in the high scores and the event log was written
+    pub is_run_recorded: bool,
     pub selected_tile: Option<TileMapPos>,
+    #[serde(with = "map_as_pairs")]
     pub trap_tiles: HashMap<TileMapPos, TrapTile>,
+    /// Invalidated whenever `trap_tiles` or `tile_map` change
+    #[serde(skip)]
+    pub distance_fields: DistanceFields,
     pub run_length_seconds: f32,
+    pub tile_map: TileMap,
+    #[serde(skip)]
+    pub map_path: String,
+    #[serde(skip)]
+    pub editor: Option<MapEditor>,
+    #[serde(skip)]
+    pub last_autosave_secs: f32,
+    pub seed: u64,
+    /// Starts from `seed`, every random outcome of the run draws from it
+    pub rng: RunRng,
+    pub mode: GameMode,
+    pub statistics: RunStatistics,
+    pub statistics_history: Vec<StatisticsSample>,
+    #[serde(skip)]
+    pub high_scores: HighScores,
+    #[serde(skip)]
+    pub show_high_scores: bool,
+    #[serde(skip)]
+    pub show_dps_overlay: bool,
+    #[serde(skip)]
+    pub event_bus: EventBus,
+    #[serde(skip)]
+    pub event_log: EventLog,
+    #[serde(skip)]
+    pub event_log_path: Option<String>,
+    #[serde(skip)]
+    pub floating_texts: FloatingTexts,
+    #[serde(skip)]
+    pub effects: Effects,
+    #[serde(skip)]
+    pub audio: Audio,
+    #[serde(skip)]
+    pub hud: Hud,
+    #[serde(skip)]
+    pub build_mode: Option<BuildMode>,
+    #[serde(skip)]
+    pub toasts: Toasts,
+    #[serde(skip)]
+    pub undo_history: UndoHistory,
+    #[serde(skip)]
+    pub game_speed: GameSpeed,
+    #[serde(skip)]
+    pub keybindings: Keybindings,
+    #[serde(skip)]
+    pub touch: TouchInput,
+    #[serde(skip)]
+    pub camera: MapCamera,
+    #[serde(skip)]
+    pub flow: GameFlow,
 }
 
 impl Default for GameState {
     fn default() -> Self {
+        let seed = thread_rng().gen();
         Self {
             // Trick mentioned here: https://comfyengine.org/book/context/
             delta: 0., // Should be set each iteration
@@ -31,27 +90,122 @@ impl Default for GameState {
             },
             is_paused: false,
             is_game_over: false,
+            is_run_recorded: false,
             selected_tile: None,
             trap_tiles: HashMap::default(),
+            distance_fields: DistanceFields::default(),
             run_length_seconds: 0.,
+            tile_map: TileMap::default(),
+            map_path: String::new(),
+            editor: None,
+            last_autosave_secs: 0.,
+            seed,
+            rng: RunRng::new(seed),
+            mode: GameMode::Normal,
+            statistics: RunStatistics::default(),
+            statistics_history: vec![],
+            high_scores: HighScores::default(),
+            show_high_scores: false,
+            show_dps_overlay: false,
+            event_bus: EventBus::default(),
+            event_log: EventLog::default(),
+            event_log_path: None,
+            floating_texts: FloatingTexts::default(),
+            effects: Effects::default(),
+            audio: Audio::default(),
+            hud: Hud::default(),
+            build_mode: None,
+            toasts: Toasts::default(),
+            undo_history: UndoHistory::default(),
+            game_speed: GameSpeed::default(),
+            keybindings: Keybindings::default(),
+            touch: TouchInput::default(),
+            camera: MapCamera::default(),
+            flow: GameFlow::default(),
         }
     }
 }
 
 impl GameState {
     pub fn new(_c: &EngineState) -> Self {
-        Self::default()
+        let mut toasts = Toasts::default();
+        let high_scores = HighScores::load().unwrap_or_else(|err| {
+            toasts.error(format!("Failed to load high scores: {}", err));
+            HighScores::default()
+        });
+        let audio_settings = AudioSettings::load().unwrap_or_else(|err| {
+            toasts.error(format!("Failed to load audio settings: {}", err));
+            AudioSettings::default()
+        });
+        let keybindings = Keybindings::load().unwrap_or_else(|err| {
+            toasts.error(format!("Failed to load keybindings: {}", err));
+            Keybindings::default()
+        });
+        let mut state = Self {
+            high_scores,
+            audio: Audio::new(audio_settings),
+            keybindings,
+            toasts,
+            ..Self::default()
+        };
+        state.subscribe_to_events();
+        state.open_main_menu();
+        state
+    }
+
+    /// Every system reacting to events subscribes here, kept across runs
+    fn subscribe_to_events(&mut self) {
+        let event_bus = &mut self.event_bus;
+        event_bus.subscribe(|state, time_secs, event| state.statistics.on_event(time_secs, event));
+        event_bus.subscribe(|state, time_secs, event| state.event_log.on_event(time_secs, event));
+        event_bus
+            .subscribe(|state, time_secs, event| state.floating_texts.on_event(time_secs, event));
+        event_bus.subscribe(|state, time_secs, event| state.audio.on_event(time_secs, event));
+        event_bus.subscribe(|state, _, event| state.toast_event(event));
     }
 
     pub fn restart(&mut self) {
-        *self = Self::default();
+        let tile_map = std::mem::take(&mut self.tile_map);
+        self.restart_with_map(tile_map);
+    }
+
+    pub fn restart_with_map(&mut self, tile_map: TileMap) {
+        self.restart_with(tile_map, thread_rng().gen(), self.mode);
+    }
+
+    /// Starts a new run, the same seed and map replay the same enemies
+    pub fn restart_with(&mut self, tile_map: TileMap, seed: u64, mode: GameMode) {
+        self.write_unfinished_event_log();
+        *self = Self {
+            tile_map,
+            seed,
+            rng: RunRng::new(seed),
+            mode,
+            map_path: std::mem::take(&mut self.map_path),
+            high_scores: std::mem::take(&mut self.high_scores),
+            show_dps_overlay: self.show_dps_overlay,
+            event_log_path: self.event_log_path.take(),
+            audio: std::mem::take(&mut self.audio),
+            hud: std::mem::take(&mut self.hud),
+            toasts: std::mem::take(&mut self.toasts),
+            game_speed: self.game_speed,
+            keybindings: std::mem::take(&mut self.keybindings),
+            touch: std::mem::take(&mut self.touch),
+            camera: std::mem::take(&mut self.camera),
+            flow: std::mem::take(&mut self.flow),
+            event_bus: self.event_bus.for_next_run(),
+            ..Self::default()
+        };
+        self.start_playing();
     }
 
     pub fn passage_of_time(&mut self) {
-        self.delta = delta();
+        self.delta = self.simulation_delta();
         if !self.is_paused {
-            self.run_length_seconds += self.delta
+            self.run_length_seconds += self.delta;
+            self.undo_history.expire_paused_actions();
         }
+        self.event_bus.set_time(self.run_length_seconds);
     }
 
     pub fn draw(&self) {
@@ -59,44 +213,100 @@ impl GameState {
         self.draw_tilemap();
         self.draw_enemies();
         self.draw_traps();
+        self.draw_build_preview();
+        self.draw_dps_overlay();
+        self.effects.draw();
+        self.floating_texts.draw();
     }
 
-    pub fn check_game_over(&mut self) {
-        if self.is_game_over {
-            egui::Window::new("GAME OVER")
-                .anchor(Align2::CENTER_TOP, [0., 0.])
-                .collapsible(false)
-                .show(egui(), |ui| {
-                    ui.label(&format!(
-                        "You have lasted for {}",
-                        self.run_length_formatted()
-                    ));
-                    if ui.button("Restart game").clicked() {
-                        self.restart()
-                    }
-                });
+    pub fn check_inputs(&mut self) {
+        self.camera_inputs();
+        if self.editor.is_some() {
+            self.editor_inputs();
+            return;
         }
-    }
 
-    pub fn check_inputs(&mut self) {
-        if is_mouse_button_pressed(MouseButton::Left) {
-            self.selected_tile = tile_map::TileMapPos::from_absolute(mouse_world())
+        self.touch_inputs();
+        if !self.is_game_over {
+            self.undo_inputs();
+            self.game_speed_inputs();
+            self.selection_inputs();
+            self.upgrade_and_sell_inputs();
+        }
+        let is_building = !self.is_game_over && self.build_mode_inputs();
+        // Clicks on the HUD would otherwise deselect the tile being built on
+        if !is_building
+            && is_mouse_button_pressed(MouseButton::Left)
+            && !egui().wants_pointer_input()
+        {
+            self.selected_tile = self.tile_map.pos_from_absolute(mouse_world())
         }
 
-        if is_key_pressed(KeyCode::P) {
+        if self.keybindings.is_pressed(Action::Pause) {
             self.is_paused = !self.is_paused;
         }
+        self.hud_inputs();
     }
 
     pub fn general_debug_ui(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.checkbox(&mut self.is_paused, "Paused");
-            ui.label(&format!("FPS: {}", get_fps()));
-            ui.label(&format!("\tRun Length: {}", self.run_length_formatted()));
+            ui.checkbox(&mut self.show_dps_overlay, "DPS overlay");
+            ui.label(format!("FPS: {}", get_fps()));
+            ui.label(format!("\tRun Length: {}", self.run_length_formatted()));
+        });
+        ui.horizontal(|ui| {
+            ui.label(format!("Mode: {}\tSeed: {}", self.mode.name(), self.seed));
+            for mode in GameMode::ALL {
+                if ui.button(format!("New {} run", mode.name())).clicked() {
+                    let tile_map = std::mem::take(&mut self.tile_map);
+                    self.restart_with(tile_map, thread_rng().gen(), mode);
+                }
+            }
+            if ui.button("High scores").clicked() {
+                self.show_high_scores = true;
+            }
         });
     }
 
     pub fn ui(&mut self) {
+        if self.flow.is_in_run() {
+            self.hud_ui();
+        }
+        let is_playing = self.flow.screen == Screen::Playing;
+        if is_playing && self.hud.show_debug_panels {
+            self.debug_panels_ui();
+        }
+        self.editor_ui();
+        self.flow_ui();
+        self.high_scores_ui();
+        self.keybindings_ui();
+        if is_playing {
+            self.tile_info_ui();
+        }
+        self.toasts.ui();
+        self.update_camera_free_rect();
+    }
+
+    fn debug_panels_ui(&mut self) {
+        // A phone has no room for the spreadsheet under the map, so it floats above it
+        if is_compact_layout() {
+            let screen = egui().screen_rect();
+            let mut is_open = true;
+            egui::Window::new("Debug panels")
+                .open(&mut is_open)
+                .collapsible(true)
+                .default_size(screen.size() * 0.9)
+                .max_height(screen.height() * 0.7)
+                .vscroll(true)
+                .show(egui(), |ui| {
+                    self.general_debug_panel_ui(ui);
+                    ui.separator();
+                    self.spawner_debug_panel_ui(ui);
+                });
+            self.hud.show_debug_panels = is_open;
+            return;
+        }
         egui::panel::TopBottomPanel::bottom("spreadsheet")
             .min_height(300.)
             .show(egui(), |ui| {
@@ -104,26 +314,52 @@ impl GameState {
                     let left_panel = &mut columns[0];
                     egui::ScrollArea::vertical()
                         .id_source("Left scroll")
-                        .show(left_panel, |ui| {
-                            self.general_debug_ui(ui);
-                            self.player.debug_ui(ui);
-                            self.selected_tile_debug_ui(ui);
-                        });
+                        .show(left_panel, |ui| self.general_debug_panel_ui(ui));
 
                     let right_panel = &mut columns[1];
                     egui::ScrollArea::vertical()
                         .id_source("Right scroll")
-                        .show(right_panel, |ui| self.enemy_spawner.debug_ui(ui));
+                        .show(right_panel, |ui| self.spawner_debug_panel_ui(ui));
                 });
             });
     }
 
+    fn general_debug_panel_ui(&mut self, ui: &mut egui::Ui) {
+        self.general_debug_ui(ui);
+        self.save_debug_ui(ui);
+        self.event_log_debug_ui(ui);
+        self.audio_debug_ui(ui);
+        let gold_before = self.player.gold.value;
+        self.player.debug_ui(ui, self.mode == GameMode::Sandbox);
+        if self.player.gold.value != gold_before {
+            self.event_bus.emit(GameEvent::GoldChanged {
+                amount: self.player.gold.value - gold_before,
+                reason: GoldChangeReason::Cheat,
+            });
+        }
+        self.selected_tile_debug_ui(ui);
+        self.map_debug_ui(ui);
+    }
+
+    fn spawner_debug_panel_ui(&mut self, ui: &mut egui::Ui) {
+        match self.mode {
+            GameMode::Normal => {
+                ui.heading("Enemy spawner is only editable in sandbox mode");
+            }
+            GameMode::Sandbox => self.enemy_spawner.debug_ui(ui),
+        }
+    }
+
     pub fn run_length_formatted(&self) -> String {
-        format!(
-            "{:02.0}:{:02.0}:{:02.0}",
-            self.run_length_seconds.div_euclid(60.),
-            self.run_length_seconds.div_euclid(1.),
-            self.run_length_seconds.rem_euclid(1.) * 100.,
-        )
+        format_run_length(self.run_length_seconds)
     }
 }
+
+pub fn format_run_length(run_length_seconds: f32) -> String {
+    format!(
+        "{:02.0}:{:02.0}:{:02.0}",
+        run_length_seconds.div_euclid(60.),
+        run_length_seconds.div_euclid(1.),
+        run_length_seconds.rem_euclid(1.) * 100.,
+    )
+}
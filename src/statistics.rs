@@ -0,0 +1,241 @@
+use comfy::{egui_plot::*, *};
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+const SAMPLE_INTERVAL_SECS: f32 = 1.;
+/// How far back the live damage per second of traps looks
+pub const DPS_WINDOW_SECS: f32 = 5.;
+
+/// The trap responsible for some damage. Keyed by tile and kind only, so a trap
+/// of the same kind rebuilt on the tile continues the statistics of the one before,
+/// and an undone trap keeps the damage it dealt with only the gold it did not refund.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DamageSource {
+    pub tile: TileMapPos,
+    pub trap_kind: TrapKind,
+}
+
+impl DamageSource {
+    pub fn name(&self) -> String {
+        format!(
+            "{} on ({},{})",
+            self.trap_kind.name(),
+            self.tile.x,
+            self.tile.y
+        )
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TrapStatistics {
+    pub damage_dealt: f32,
+    pub kills: u32,
+    pub gold_spent: f32,
+    /// Damage dealt per point of run time within the last `DPS_WINDOW_SECS`
+    #[serde(skip)]
+    recent_damage: VecDeque<(f32, f32)>,
+}
+
+impl TrapStatistics {
+    pub fn recent_dps(&self, now_secs: f32) -> f32 {
+        let recent_damage: f32 = self
+            .recent_damage
+            .iter()
+            .filter(|(time_secs, _)| now_secs - time_secs <= DPS_WINDOW_SECS)
+            .map(|(_, damage)| damage)
+            .sum();
+        recent_damage / DPS_WINDOW_SECS
+    }
+
+    pub fn damage_per_gold(&self) -> Option<f32> {
+        (self.gold_spent > 0.).then(|| self.damage_dealt / self.gold_spent)
+    }
+}
+
+/// Counters collected over a single run
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct RunStatistics {
+    pub kills: u32,
+    pub gold_earned: f32,
+    pub traps_built: u32,
+    #[serde(with = "map_as_pairs")]
+    pub per_trap: HashMap<DamageSource, TrapStatistics>,
+}
+
+impl RunStatistics {
+    pub fn on_event(&mut self, time_secs: f32, event: &GameEvent) {
+        match event {
+            GameEvent::EnemyKilled { by, .. } => self.record_kill(*by),
+            GameEvent::TrapBuilt { source, gold_spent } => {
+                self.traps_built += 1;
+                self.per_trap.entry(*source).or_default().gold_spent += gold_spent;
+            }
+            GameEvent::TrapRemoved {
+                source,
+                gold_refunded,
+            } => {
+                self.traps_built = self.traps_built.saturating_sub(1);
+                self.per_trap.entry(*source).or_default().gold_spent -= gold_refunded;
+            }
+            GameEvent::TrapUpgraded { source, gold_spent } => {
+                self.per_trap.entry(*source).or_default().gold_spent += gold_spent;
+            }
+            GameEvent::TrapSold {
+                source,
+                gold_refunded,
+            } => self.per_trap.entry(*source).or_default().gold_spent -= gold_refunded,
+            GameEvent::DamageDealt { source, damage, .. } => {
+                self.record_damage(*source, *damage, time_secs)
+            }
+            GameEvent::GoldChanged {
+                amount,
+                reason: GoldChangeReason::Kill | GoldChangeReason::Interest,
+            } => self.gold_earned += amount,
+            _ => {}
+        }
+    }
+}
+
+impl RunStatistics {
+    fn record_damage(&mut self, source: DamageSource, damage: f32, now_secs: f32) {
+        let trap_statistics = self.per_trap.entry(source).or_default();
+        trap_statistics.damage_dealt += damage;
+        match trap_statistics.recent_damage.back_mut() {
+            Some((time_secs, recent)) if *time_secs == now_secs => *recent += damage,
+            _ => trap_statistics.recent_damage.push_back((now_secs, damage)),
+        }
+        while trap_statistics
+            .recent_damage
+            .front()
+            .is_some_and(|(time_secs, _)| now_secs - time_secs > DPS_WINDOW_SECS)
+        {
+            trap_statistics.recent_damage.pop_front();
+        }
+    }
+
+    fn record_kill(&mut self, source: Option<DamageSource>) {
+        self.kills += 1;
+        if let Some(source) = source {
+            self.per_trap.entry(source).or_default().kills += 1;
+        }
+    }
+
+    pub fn kills_per_trap_kind(&self) -> HashMap<TrapKind, u32> {
+        let mut result = HashMap::new();
+        for (source, trap_statistics) in self.per_trap.iter() {
+            *result.entry(source.trap_kind).or_default() += trap_statistics.kills;
+        }
+        result
+    }
+}
+
+/// State of the run at one point in time, taken every `SAMPLE_INTERVAL_SECS`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StatisticsSample {
+    pub time_secs: f32,
+    pub gold: f32,
+    pub player_hp: f32,
+    pub enemy_count: usize,
+    pub enemy_maximum_hp: f32,
+    pub kills_per_trap_kind: HashMap<TrapKind, u32>,
+}
+
+impl GameState {
+    pub fn collect_statistics(&mut self) {
+        if self.is_paused {
+            return;
+        }
+        let last_sample_secs = self
+            .statistics_history
+            .last()
+            .map_or(f32::NEG_INFINITY, |sample| sample.time_secs);
+        if self.run_length_seconds - last_sample_secs < SAMPLE_INTERVAL_SECS {
+            return;
+        }
+        self.statistics_history.push(StatisticsSample {
+            time_secs: self.run_length_seconds,
+            gold: self.player.gold.value,
+            player_hp: self.player.hp.current(),
+            enemy_count: self.enemies.len(),
+            enemy_maximum_hp: self.enemy_spawner.maximum_hp(),
+            kills_per_trap_kind: self.statistics.kills_per_trap_kind(),
+        });
+    }
+
+    pub fn statistics_ui(&self, ui: &mut egui::Ui) {
+        let history = &self.statistics_history;
+        let series = |value: fn(&StatisticsSample) -> f32| {
+            history
+                .iter()
+                .map(|sample| [sample.time_secs as f64, value(sample) as f64])
+                .collect::<PlotPoints>()
+        };
+
+        ui.columns(2, |columns| {
+            small_plot(&mut columns[0], "Gold", |plot_ui| {
+                plot_ui.line(Line::new(series(|sample| sample.gold)).name("Gold"));
+            });
+            small_plot(&mut columns[0], "Player HP", |plot_ui| {
+                plot_ui.line(Line::new(series(|sample| sample.player_hp)).name("Player HP"));
+            });
+            small_plot(&mut columns[1], "Enemies", |plot_ui| {
+                plot_ui.line(
+                    Line::new(series(|sample| sample.enemy_count as f32)).name("Enemy count"),
+                );
+            });
+            small_plot(&mut columns[1], "Enemy maximum HP", |plot_ui| {
+                plot_ui.line(
+                    Line::new(series(|sample| sample.enemy_maximum_hp)).name("Enemy maximum HP"),
+                );
+            });
+        });
+        small_plot(ui, "Kills per trap", |plot_ui| {
+            for trap_kind in TrapKind::ALL {
+                let points = history
+                    .iter()
+                    .map(|sample| {
+                        let kills = sample.kills_per_trap_kind.get(&trap_kind).copied();
+                        [sample.time_secs as f64, kills.unwrap_or_default() as f64]
+                    })
+                    .collect::<PlotPoints>();
+                plot_ui.line(
+                    Line::new(points)
+                        .color(trap_kind.color())
+                        .name(trap_kind.name()),
+                );
+            }
+        });
+
+        ui.separator();
+        let mut per_trap = self.statistics.per_trap.iter().collect_vec();
+        per_trap.sort_by_key(|(_, trap_statistics)| {
+            std::cmp::Reverse(OrderedFloat(trap_statistics.damage_dealt))
+        });
+        egui::Grid::new("Per trap statistics")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Trap");
+                ui.label("Damage dealt");
+                ui.label("Kills");
+                ui.end_row();
+                for (source, trap_statistics) in per_trap {
+                    ui.label(source.name());
+                    ui.label(format!("{:.0}", trap_statistics.damage_dealt));
+                    ui.label(trap_statistics.kills.to_string());
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+fn small_plot(ui: &mut egui::Ui, name: &str, build_fn: impl FnOnce(&mut PlotUi)) {
+    ui.label(name);
+    Plot::new(name)
+        .height(120.)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .legend(Legend::default())
+        .show(ui, build_fn);
+}
@@ -0,0 +1,154 @@
+use comfy::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Key of the in-progress run
+pub const SAVE_KEY: &str = "save";
+
+/// Persistent key-value store, `localStorage` in the browser and files on native
+pub trait Storage {
+    fn read(&self, key: &str) -> Result<Option<String>>;
+    fn write(&self, key: &str, value: &str) -> Result<()>;
+    fn remove(&self, key: &str) -> Result<()>;
+
+    fn read_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>>
+    where
+        Self: Sized,
+    {
+        match self.read(key)? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn write_json<T: Serialize>(&self, key: &str, value: &T) -> Result<()>
+    where
+        Self: Sized,
+    {
+        self.write(key, &serde_json::to_string(value)?)
+    }
+}
+
+/// Stores every key as a JSON file in the user's data directory
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+    directory: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+    pub fn new() -> Result<Self> {
+        let Some(data_dir) = dirs::data_dir() else {
+            bail!("Could not find the user data directory");
+        };
+        Ok(Self {
+            directory: data_dir.join("trapify"),
+        })
+    }
+
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        self.directory.join(format!("{}.json", key))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Storage for FileStorage {
+    fn read(&self, key: &str) -> Result<Option<String>> {
+        match std::fs::read_to_string(self.path(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+        std::fs::write(self.path(key), value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(key)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stores every key in the browser's `localStorage`, prefixed to avoid clashes
+/// with other pages on the same origin
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage {
+    storage: web_sys::Storage,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    pub fn new() -> Result<Self> {
+        let storage = web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| anyhow::anyhow!("localStorage is not available"))?;
+        Ok(Self { storage })
+    }
+
+    fn key(key: &str) -> String {
+        format!("trapify.{}", key)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Storage for LocalStorage {
+    fn read(&self, key: &str) -> Result<Option<String>> {
+        self.storage
+            .get_item(&Self::key(key))
+            .map_err(|err| anyhow::anyhow!("Failed to read from localStorage: {:?}", err))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<()> {
+        self.storage
+            .set_item(&Self::key(key), value)
+            .map_err(|err| anyhow::anyhow!("Failed to write to localStorage: {:?}", err))
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.storage
+            .remove_item(&Self::key(key))
+            .map_err(|err| anyhow::anyhow!("Failed to remove from localStorage: {:?}", err))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type PlatformStorage = FileStorage;
+#[cfg(target_arch = "wasm32")]
+pub type PlatformStorage = LocalStorage;
+
+/// The storage of the platform the game is running on
+pub fn storage() -> Result<PlatformStorage> {
+    PlatformStorage::new()
+}
+
+/// Offers the content as a file download through a temporary link
+#[cfg(target_arch = "wasm32")]
+pub fn download(file_name: &str, content: &str) -> Result<()> {
+    use wasm_bindgen::JsCast;
+
+    let to_error = |err: wasm_bindgen::JsValue| anyhow::anyhow!("{:?}", err);
+    let parts = js_sys::Array::of1(&content.into());
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_("text/plain");
+    let blob =
+        web_sys::Blob::new_with_str_sequence_and_options(&parts, &options).map_err(to_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(to_error)?;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| anyhow::anyhow!("No document to download from"))?;
+    let anchor = document
+        .create_element("a")
+        .map_err(to_error)?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(|_| anyhow::anyhow!("Failed to create a link"))?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url).map_err(to_error)
+}
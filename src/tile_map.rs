@@ -1,150 +1,363 @@
 use comfy::*;
+use serde::{Deserialize, Serialize};
 
-use crate::GameState;
+use crate::{draw_sprite_frame, GameState, TrapKind, Wave};
 
 pub const TILE_SIZE: f32 = 1.; // This is in world coordinates
-pub const ROWS: u32 = 5;
-pub const COLUMNS: u32 = 28; // ZOOM is setup to 30, 1 margin on both sides
+pub const MARGIN: f32 = 1.; // Empty space around the map, in world coordinates
 
-pub fn draw() {
-    // TODO: 1/64 does not work well in general
-    let draw_grid_line = |p1, p2| draw_line(p1, p2, 1. / 64., DARKGRAY, 1);
+const BUNDLED_MAP_FILES: [&str; 2] = [
+    include_str!("../maps/default.json"),
+    include_str!("../maps/crossroads.json"),
+];
 
-    let x_start = x_min();
-    let x_end = x_max();
-    for row in 0..=ROWS {
-        let y = y_into_absolute_start(row);
-        draw_grid_line(Vec2::new(x_start, y), Vec2::new(x_end, y))
-    }
+/// Maps shipped with the game, available on every platform, parsed once
+pub static BUNDLED_MAPS: Lazy<Vec<TileMap>> = Lazy::new(|| {
+    BUNDLED_MAP_FILES
+        .iter()
+        .map(|json| TileMap::parse(json).expect("Bundled maps are valid"))
+        .collect()
+});
 
-    let y_start = y_min();
-    let y_end = y_max();
-    for column in 0..=COLUMNS {
-        let x = x_into_absolute_start(column);
-        draw_grid_line(Vec2::new(x, y_start), Vec2::new(x, y_end))
-    }
+/// On-disk representation of a map, each string in `tiles` is a single row, top row first.
+///
+/// Tile legend:
+/// - `.` buildable ground
+/// - `-` walkable, but unbuildable
+/// - `#` blocked, not part of the map
+/// - `S` spawn point
+/// - `E` exit
+/// - `~` mud, `*` ice, `^` rock, `+` fertile soil
+/// - `1` to `6` amplifier of the trap at that position in `TrapKind::ALL`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MapDefinition {
+    pub name: String,
+    pub tiles: Vec<String>,
+    #[serde(default)]
+    pub waves: Vec<Wave>,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub struct TileMapPos {
-    pub x: u32,
-    pub y: u32,
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Terrain {
+    Ground,
+    /// Slows down walking enemies
+    Mud,
+    /// Speeds up walking enemies
+    Ice,
+    /// Cannot be built on
+    Rock,
+    /// Traps built here are stronger
+    Fertile,
+    /// Traps of the given kind built here are a lot stronger
+    Amplifier(TrapKind),
 }
 
-pub fn x_min() -> f32 {
-    main_camera().screen_top_left().x + TILE_SIZE // MARGIN
-}
+impl Terrain {
+    pub fn from_char(c: char) -> Option<Terrain> {
+        match c {
+            '~' => Some(Terrain::Mud),
+            '*' => Some(Terrain::Ice),
+            '^' => Some(Terrain::Rock),
+            '+' => Some(Terrain::Fertile),
+            _ => {
+                let index = c.to_digit(10)?.checked_sub(1)?;
+                let trap_kind = TrapKind::ALL.get(index as usize)?;
+                Some(Terrain::Amplifier(*trap_kind))
+            }
+        }
+    }
 
-pub fn x_max() -> f32 {
-    x_min() + (COLUMNS as f32) * TILE_SIZE
-}
+    pub fn to_char(&self) -> char {
+        match self {
+            Terrain::Ground => '.',
+            Terrain::Mud => '~',
+            Terrain::Ice => '*',
+            Terrain::Rock => '^',
+            Terrain::Fertile => '+',
+            Terrain::Amplifier(trap_kind) => {
+                let index = TrapKind::ALL.iter().position(|kind| kind == trap_kind);
+                char::from_digit(index.unwrap_or(0) as u32 + 1, 10).unwrap_or('.')
+            }
+        }
+    }
 
-pub fn x_from_absolute(x: f32) -> Option<u32> {
-    let x = (x - x_min()).div_euclid(TILE_SIZE);
-    if x >= 0. && x < COLUMNS as f32 {
-        Some(x as u32)
-    } else {
-        None
+    pub fn name(&self) -> String {
+        match self {
+            Terrain::Ground => "Ground".to_string(),
+            Terrain::Mud => "Mud".to_string(),
+            Terrain::Ice => "Ice".to_string(),
+            Terrain::Rock => "Rock".to_string(),
+            Terrain::Fertile => "Fertile".to_string(),
+            Terrain::Amplifier(trap_kind) => format!("{} amplifier", trap_kind.name()),
+        }
     }
-}
 
-pub fn x_into_absolute_start(x: u32) -> f32 {
-    x_min() + (x as f32) * TILE_SIZE
-}
+    pub fn speed_multiplier(&self) -> f32 {
+        match self {
+            Terrain::Mud => 0.5,
+            Terrain::Ice => 1.5,
+            _ => 1.,
+        }
+    }
 
-pub fn x_into_absolute_mid(x: u32) -> f32 {
-    x_into_absolute_start(x) + TILE_SIZE / 2.
-}
+    pub fn trap_multiplier(&self, trap_kind: TrapKind) -> f32 {
+        match self {
+            Terrain::Fertile => 1.25,
+            Terrain::Amplifier(amplified) if *amplified == trap_kind => 1.5,
+            _ => 1.,
+        }
+    }
 
-pub fn x_into_absolute_end(x: u32) -> f32 {
-    x_into_absolute_start(x) + TILE_SIZE
-}
+    pub fn sprite_name(&self) -> &'static str {
+        match self {
+            Terrain::Ground => "tiles/ground",
+            Terrain::Mud => "tiles/mud",
+            Terrain::Ice => "tiles/ice",
+            Terrain::Rock => "tiles/rock",
+            Terrain::Fertile => "tiles/fertile",
+            Terrain::Amplifier(_) => "tiles/amplifier",
+        }
+    }
 
-pub fn y_max() -> f32 {
-    main_camera().screen_top_left().y - TILE_SIZE // margin
+    /// Tint of the tile sprite, amplifiers share a sprite colored by their trap
+    pub fn sprite_tint(&self) -> Color {
+        match self {
+            Terrain::Amplifier(trap_kind) => WHITE.mix(trap_kind.color(), 0.5),
+            _ => WHITE,
+        }
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        match self {
+            Terrain::Ground => None,
+            Terrain::Mud => Some(BROWN.alpha(0.5)),
+            Terrain::Ice => Some(AZURE),
+            Terrain::Rock => Some(DARKGRAY),
+            Terrain::Fertile => Some(YELLOW_GREEN.alpha(0.5)),
+            Terrain::Amplifier(trap_kind) => Some(trap_kind.color().alpha(0.3)),
+        }
+    }
 }
 
-pub fn y_min() -> f32 {
-    y_max() - (ROWS as f32) * TILE_SIZE
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "MapDefinition", try_from = "MapDefinition")]
+pub struct TileMap {
+    pub name: String,
+    pub columns: u32,
+    pub rows: u32,
+    pub blocked: HashSet<TileMapPos>,
+    pub unbuildable: HashSet<TileMapPos>,
+    pub spawns: Vec<TileMapPos>,
+    pub exits: HashSet<TileMapPos>,
+    pub terrain: HashMap<TileMapPos, Terrain>,
+    pub waves: Vec<Wave>,
 }
 
-pub fn y_from_absolute(y: f32) -> Option<u32> {
-    let y = (y - y_min()).div_euclid(TILE_SIZE);
-    if y >= 0. && y < ROWS as f32 {
-        Some(y as u32)
-    } else {
-        None
+impl From<TileMap> for MapDefinition {
+    fn from(tile_map: TileMap) -> Self {
+        tile_map.to_definition()
     }
 }
 
-pub fn y_into_absolute_start(y: u32) -> f32 {
-    y_min() + (y as f32) * TILE_SIZE
-}
+impl TryFrom<MapDefinition> for TileMap {
+    type Error = anyhow::Error;
 
-pub fn y_into_absolute_mid(y: u32) -> f32 {
-    y_into_absolute_start(y) + TILE_SIZE / 2.
+    fn try_from(definition: MapDefinition) -> Result<Self> {
+        Self::from_definition(&definition)
+    }
 }
 
-pub fn y_into_absolute_end(y: u32) -> f32 {
-    y_into_absolute_start(y) + TILE_SIZE
+impl Default for TileMap {
+    fn default() -> Self {
+        BUNDLED_MAPS[0].clone()
+    }
 }
 
-impl TileMapPos {
-    pub fn new(x: u32, y: u32) -> TileMapPos {
-        Self { x, y }
+impl TileMap {
+    pub fn parse(json: &str) -> Result<Self> {
+        Self::from_definition(&serde_json::from_str(json)?)
     }
 
-    pub fn from_absolute(pos: Vec2) -> Option<TileMapPos> {
-        let x = x_from_absolute(pos.x)?;
-        let y = y_from_absolute(pos.y)?;
-        Some(TileMapPos { x, y })
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
     }
 
-    pub fn into_absolute_start(self) -> Vec2 {
-        Vec2 {
-            x: x_into_absolute_start(self.x),
-            y: y_into_absolute_start(self.y),
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.to_definition())?)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn from_definition(definition: &MapDefinition) -> Result<Self> {
+        let rows = definition.tiles.len() as u32;
+        // Shorter rows are padded with blocked tiles, which allows for non-rectangular maps
+        let columns = definition
+            .tiles
+            .iter()
+            .map(|row| row.chars().count() as u32)
+            .max()
+            .unwrap_or(0);
+        if rows == 0 || columns == 0 {
+            bail!("Map '{}' has no tiles", definition.name);
+        }
+
+        let mut tile_map = TileMap {
+            name: definition.name.clone(),
+            columns,
+            rows,
+            blocked: HashSet::new(),
+            unbuildable: HashSet::new(),
+            spawns: vec![],
+            exits: HashSet::new(),
+            terrain: HashMap::new(),
+            waves: definition.waves.clone(),
+        };
+        for (row_index, row) in definition.tiles.iter().enumerate() {
+            let y = rows - 1 - row_index as u32;
+            let mut chars = row.chars();
+            for x in 0..columns {
+                let tile_map_pos = TileMapPos::new(x, y);
+                match chars.next().unwrap_or('#') {
+                    '.' => {}
+                    '-' => {
+                        tile_map.unbuildable.insert(tile_map_pos);
+                    }
+                    '#' => {
+                        tile_map.blocked.insert(tile_map_pos);
+                    }
+                    'S' => tile_map.spawns.push(tile_map_pos),
+                    'E' => {
+                        tile_map.exits.insert(tile_map_pos);
+                    }
+                    other => match Terrain::from_char(other) {
+                        Some(terrain) => {
+                            tile_map.terrain.insert(tile_map_pos, terrain);
+                        }
+                        None => bail!("Unknown tile '{}' at ({}, {})", other, x, y),
+                    },
+                }
+            }
+        }
+
+        if tile_map.spawns.is_empty() {
+            bail!("Map '{}' has no spawn point", tile_map.name);
+        }
+        if tile_map.exits.is_empty() {
+            bail!("Map '{}' has no exit", tile_map.name);
+        }
+        for spawn in tile_map.spawns.iter() {
+            let reachable = tile_map.area_til_distance(*spawn, columns * rows);
+            if !reachable.iter().any(|tile| tile_map.exits.contains(tile)) {
+                bail!("Spawn at ({}, {}) cannot reach any exit", spawn.x, spawn.y);
+            }
         }
+        Ok(tile_map)
     }
 
-    pub fn into_absolute_end(self) -> Vec2 {
-        Vec2 {
-            x: x_into_absolute_end(self.x),
-            y: y_into_absolute_end(self.y),
+    pub fn to_definition(&self) -> MapDefinition {
+        let tiles = (0..self.rows)
+            .rev()
+            .map(|y| {
+                (0..self.columns)
+                    .map(|x| {
+                        let tile_map_pos = TileMapPos::new(x, y);
+                        if self.blocked.contains(&tile_map_pos) {
+                            '#'
+                        } else if self.spawns.contains(&tile_map_pos) {
+                            'S'
+                        } else if self.exits.contains(&tile_map_pos) {
+                            'E'
+                        } else if self.unbuildable.contains(&tile_map_pos) {
+                            '-'
+                        } else {
+                            self.terrain_at(tile_map_pos).to_char()
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        MapDefinition {
+            name: self.name.clone(),
+            tiles,
+            waves: self.waves.clone(),
         }
     }
 
-    pub fn into_absolute_mid(self) -> Vec2 {
-        Vec2 {
-            x: x_into_absolute_mid(self.x),
-            y: y_into_absolute_mid(self.y),
+    /// Whether the tile is within the map's bounds, regardless of it being blocked
+    pub fn in_bounds(&self, tile_map_pos: TileMapPos) -> bool {
+        tile_map_pos.x < self.columns && tile_map_pos.y < self.rows
+    }
+
+    /// Whether the tile is a part of the map
+    pub fn contains(&self, tile_map_pos: TileMapPos) -> bool {
+        self.in_bounds(tile_map_pos) && !self.blocked.contains(&tile_map_pos)
+    }
+
+    pub fn is_buildable(&self, tile_map_pos: TileMapPos) -> bool {
+        self.contains(tile_map_pos)
+            && !self.unbuildable.contains(&tile_map_pos)
+            && self.terrain_at(tile_map_pos) != Terrain::Rock
+    }
+
+    pub fn terrain_at(&self, tile_map_pos: TileMapPos) -> Terrain {
+        self.terrain
+            .get(&tile_map_pos)
+            .copied()
+            .unwrap_or(Terrain::Ground)
+    }
+
+    pub fn width(&self) -> f32 {
+        self.columns as f32 * TILE_SIZE
+    }
+
+    pub fn height(&self) -> f32 {
+        self.rows as f32 * TILE_SIZE
+    }
+
+    pub fn pos_from_absolute(&self, pos: Vec2) -> Option<TileMapPos> {
+        self.bounded_pos_from_absolute(pos)
+            .filter(|tile_map_pos| self.contains(*tile_map_pos))
+    }
+
+    /// Like `pos_from_absolute`, but includes blocked tiles
+    pub fn bounded_pos_from_absolute(&self, pos: Vec2) -> Option<TileMapPos> {
+        if pos.x < 0. || pos.y < 0. {
+            return None;
         }
+        let tile_map_pos = TileMapPos::new(
+            pos.x.div_euclid(TILE_SIZE) as u32,
+            pos.y.div_euclid(TILE_SIZE) as u32,
+        );
+        self.in_bounds(tile_map_pos).then_some(tile_map_pos)
     }
 
-    pub fn neighbors(self) -> Vec<TileMapPos> {
+    pub fn neighbors(&self, tile_map_pos: TileMapPos) -> Vec<TileMapPos> {
+        let TileMapPos { x, y } = tile_map_pos;
         let mut result = Vec::new();
-        if self.x > 0 {
-            result.push(TileMapPos::new(self.x - 1, self.y));
-        }
-        if self.y > 0 {
-            result.push(TileMapPos::new(self.x, self.y - 1));
+        if x > 0 {
+            result.push(TileMapPos::new(x - 1, y));
         }
-        if self.x < COLUMNS - 1 {
-            result.push(TileMapPos::new(self.x + 1, self.y));
-        }
-        if self.y < ROWS - 1 {
-            result.push(TileMapPos::new(self.x, self.y + 1));
+        if y > 0 {
+            result.push(TileMapPos::new(x, y - 1));
         }
+        result.push(TileMapPos::new(x + 1, y));
+        result.push(TileMapPos::new(x, y + 1));
+        result.retain(|neighbor| self.contains(*neighbor));
         result
     }
 
-    pub fn l1_distance(&self, other: TileMapPos) -> u32 {
-        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
-    }
-
-    pub fn area_til_distance(self, distance: u32) -> HashSet<TileMapPos> {
+    pub fn area_til_distance(
+        &self,
+        tile_map_pos: TileMapPos,
+        distance: u32,
+    ) -> HashSet<TileMapPos> {
         let mut tried = HashSet::new();
-        let mut stack = vec![self];
+        let mut stack = vec![tile_map_pos];
         let mut result = HashSet::new();
 
         while let Some(x) = stack.pop() {
@@ -152,19 +365,91 @@ impl TileMapPos {
                 continue;
             }
             tried.insert(x);
-            if self.l1_distance(x) > distance {
+            if tile_map_pos.l1_distance(x) > distance {
                 continue;
             }
             result.insert(x);
-            stack.extend(x.neighbors())
+            stack.extend(self.neighbors(x))
         }
         result
     }
+
+    pub fn draw(&self) {
+        for x in 0..self.columns {
+            for y in 0..self.rows {
+                let tile_map_pos = TileMapPos::new(x, y);
+                if !self.contains(tile_map_pos) {
+                    continue;
+                }
+                let center = tile_map_pos.into_absolute_mid();
+                // TODO: 1/64 does not work well in general
+                draw_rect_outline(center, Vec2::splat(TILE_SIZE), 1. / 64., DARKGRAY, 1);
+
+                let terrain = self.terrain_at(tile_map_pos);
+                let has_sprite = draw_sprite_frame(
+                    terrain.sprite_name(),
+                    center,
+                    Vec2::splat(TILE_SIZE),
+                    terrain.sprite_tint(),
+                    -2,
+                    0,
+                );
+
+                let fill = if self.spawns.contains(&tile_map_pos) {
+                    Some(LIME_GREEN.alpha(0.3))
+                } else if self.exits.contains(&tile_map_pos) {
+                    Some(CRIMSON.alpha(0.3))
+                } else if self.unbuildable.contains(&tile_map_pos) {
+                    Some(GRAY)
+                } else if has_sprite {
+                    None
+                } else {
+                    terrain.color()
+                };
+                if let Some(color) = fill {
+                    draw_rect(center, Vec2::splat(TILE_SIZE), color, -1);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TileMapPos {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileMapPos {
+    pub fn new(x: u32, y: u32) -> TileMapPos {
+        Self { x, y }
+    }
+
+    pub fn into_absolute_start(self) -> Vec2 {
+        Vec2 {
+            x: self.x as f32 * TILE_SIZE,
+            y: self.y as f32 * TILE_SIZE,
+        }
+    }
+
+    pub fn into_absolute_end(self) -> Vec2 {
+        self.into_absolute_start() + Vec2::splat(TILE_SIZE)
+    }
+
+    pub fn into_absolute_mid(self) -> Vec2 {
+        self.into_absolute_start() + Vec2::splat(TILE_SIZE / 2.)
+    }
+
+    pub fn l1_distance(&self, other: TileMapPos) -> u32 {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
+    }
 }
 
 impl GameState {
     pub fn draw_tilemap(&self) {
-        draw();
+        self.fit_camera();
+        self.tile_map.draw();
+        self.draw_editor();
         if let Some(tile_map_pos) = self.selected_tile.as_ref() {
             draw_rect(
                 tile_map_pos.into_absolute_mid(),
@@ -174,4 +459,101 @@ impl GameState {
             );
         }
     }
+
+    /// Loads the map given by `--map <path>` on the command line, if any
+    pub fn load_map_from_args(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = std::env::args().skip_while(|arg| arg != "--map").nth(1) {
+            match TileMap::load(Path::new(&path)) {
+                Ok(tile_map) => self.restart_with_map(tile_map),
+                Err(err) => self
+                    .toasts
+                    .error(format!("Failed to load map {}: {}", path, err)),
+            }
+        }
+    }
+
+    pub fn map_debug_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading(format!("Map: {}", self.tile_map.name));
+        ui.separator();
+        if ui.button("Open map editor").clicked() {
+            self.open_editor();
+        }
+        ui.horizontal(|ui| {
+            for tile_map in BUNDLED_MAPS.iter() {
+                if ui.button(&tile_map.name).clicked() {
+                    self.restart_with_map(tile_map.clone());
+                }
+            }
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.map_path);
+            if ui.button("Load from file").clicked() {
+                match TileMap::load(Path::new(&self.map_path)) {
+                    Ok(tile_map) => self.restart_with_map(tile_map),
+                    Err(err) => self.toasts.error(format!("Failed to load map: {}", err)),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(tiles: &[&str]) -> MapDefinition {
+        MapDefinition {
+            name: "Test".to_string(),
+            tiles: tiles.iter().map(|row| row.to_string()).collect(),
+            waves: vec![],
+        }
+    }
+
+    #[test]
+    fn definition_round_trip() {
+        let tiles = ["#S..~#", "-.*+^E", "#.1.6#"];
+        let tile_map = TileMap::from_definition(&definition(&tiles)).unwrap();
+        assert_eq!((tile_map.columns, tile_map.rows), (6, 3));
+        assert_eq!(tile_map.spawns, vec![TileMapPos::new(1, 2)]);
+        assert!(tile_map.exits.contains(&TileMapPos::new(5, 1)));
+        assert_eq!(tile_map.terrain_at(TileMapPos::new(4, 2)), Terrain::Mud);
+        assert_eq!(
+            tile_map.terrain_at(TileMapPos::new(2, 0)),
+            Terrain::Amplifier(TrapKind::Simple)
+        );
+        assert_eq!(tile_map.to_definition().tiles, tiles);
+
+        let reparsed = TileMap::parse(&tile_map.to_json().unwrap()).unwrap();
+        assert_eq!(reparsed.to_definition().tiles, tiles);
+    }
+
+    #[test]
+    fn short_rows_are_padded_with_blocked_tiles() {
+        let tile_map = TileMap::from_definition(&definition(&["S...E", "..."])).unwrap();
+        assert_eq!(tile_map.columns, 5);
+        assert!(tile_map.contains(TileMapPos::new(2, 0)));
+        assert!(!tile_map.contains(TileMapPos::new(3, 0)));
+        assert!(!tile_map.contains(TileMapPos::new(4, 0)));
+        assert_eq!(tile_map.to_definition().tiles, ["S...E", "...##"]);
+    }
+
+    #[test]
+    fn invalid_maps_are_rejected() {
+        for tiles in [
+            &[][..],
+            &[""],
+            &["S..?E"],
+            &["....E"],
+            &["S...."],
+            &["S.#.E"],
+        ] {
+            assert!(
+                TileMap::from_definition(&definition(tiles)).is_err(),
+                "{:?} was accepted",
+                tiles
+            );
+        }
+    }
 }
@@ -0,0 +1,146 @@
+use comfy::{egui::Align2, *};
+
+use crate::*;
+
+const LIFETIME_SECS: f64 = 4.;
+/// Fading out takes the end of the lifetime
+const FADE_SECS: f64 = 1.;
+const MAX_VISIBLE: usize = 5;
+/// Scripted waves are announced this long before they start
+const WAVE_WARNING_SECS: f32 = 5.;
+/// Waves whose enemies have at least this much more HP are announced as bosses
+const BOSS_HP_MULTIPLIER: f32 = 3.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToastKind {
+    Info,
+    Warning,
+    Error,
+}
+
+impl ToastKind {
+    pub fn color(&self) -> Color {
+        match self {
+            ToastKind::Info => WHITE,
+            ToastKind::Warning => YELLOW,
+            ToastKind::Error => RED,
+        }
+    }
+}
+
+pub struct Toast {
+    pub text: String,
+    pub kind: ToastKind,
+    /// Real time rather than run length, so toasts also expire while paused
+    pub created_secs: f64,
+}
+
+/// Notifications in the corner of the screen, kept across runs
+#[derive(Default)]
+pub struct Toasts {
+    toasts: Vec<Toast>,
+}
+
+impl Toasts {
+    pub fn push(&mut self, kind: ToastKind, text: String) {
+        // Errors may happen when nobody is looking anymore, like on shutdown
+        if kind == ToastKind::Error {
+            error!("{}", text);
+        }
+        // Repeating the latest toast only keeps it around for longer
+        if let Some(latest) = self.toasts.last_mut() {
+            if latest.text == text && latest.kind == kind {
+                latest.created_secs = get_time();
+                return;
+            }
+        }
+        self.toasts.push(Toast {
+            text,
+            kind,
+            created_secs: get_time(),
+        });
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(ToastKind::Info, text.into());
+    }
+
+    pub fn warning(&mut self, text: impl Into<String>) {
+        self.push(ToastKind::Warning, text.into());
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(ToastKind::Error, text.into());
+    }
+
+    pub fn ui(&mut self) {
+        let now = get_time();
+        self.toasts
+            .retain(|toast| now - toast.created_secs < LIFETIME_SECS);
+        if self.toasts.is_empty() {
+            return;
+        }
+        let visible_from = self.toasts.len().saturating_sub(MAX_VISIBLE);
+        egui::Area::new("toasts")
+            .anchor(Align2::RIGHT_TOP, [-10., 50.])
+            .interactable(false)
+            .show(egui(), |ui| {
+                for toast in self.toasts[visible_from..].iter() {
+                    let age = now - toast.created_secs;
+                    let fade = ((LIFETIME_SECS - age) / FADE_SECS).min(1.) as f32;
+                    egui::Frame::popup(ui.style())
+                        .multiply_with_opacity(fade)
+                        .show(ui, |ui| {
+                            ui.colored_label(toast.kind.color().alpha(fade).egui(), &toast.text);
+                        });
+                }
+            });
+    }
+}
+
+impl GameState {
+    /// Turns gameplay events worth the player's attention into toasts
+    pub fn toast_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::WaveStarted { index } => {
+                let Some(wave) = self.tile_map.waves.get(*index) else {
+                    return;
+                };
+                if wave.hp_multiplier >= BOSS_HP_MULTIPLIER {
+                    self.toasts.warning(format!(
+                        "Boss wave: {} {} with {:.0}x HP",
+                        wave.count,
+                        wave.archetype.name(),
+                        wave.hp_multiplier
+                    ));
+                }
+            }
+            GameEvent::GoldChanged {
+                amount,
+                reason: GoldChangeReason::Interest,
+            } => self
+                .toasts
+                .info(format!("Received {:.0} gold of interest", amount)),
+            _ => {}
+        }
+    }
+
+    /// Warns about scripted waves shortly before they start
+    pub fn announce_waves(&mut self) {
+        if self.is_paused {
+            return;
+        }
+        let previous_secs = self.run_length_seconds - self.delta;
+        for wave in self.tile_map.waves.iter() {
+            let warning_secs = wave.start_secs - WAVE_WARNING_SECS;
+            if previous_secs < warning_secs && warning_secs <= self.run_length_seconds {
+                self.toasts.warning(format!(
+                    "Wave incoming in {:.0}s: {} {}",
+                    WAVE_WARNING_SECS,
+                    wave.count,
+                    wave.archetype.name()
+                ));
+            }
+        }
+    }
+}
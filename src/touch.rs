@@ -0,0 +1,147 @@
+use comfy::{egui::Align2, *};
+
+use crate::*;
+
+/// Holding a finger still for this long shows what is on the tile instead of tapping it
+const LONG_PRESS_SECS: f64 = 0.5;
+/// Distance in egui points a finger can move while still counting as a tap
+const TAP_SLOP: f32 = 10.;
+
+struct TouchPress {
+    start_pos: egui::Pos2,
+    start_secs: f64,
+    is_long: bool,
+    /// Moved too far or turned into a pinch, so it is neither a tap nor a long press
+    is_cancelled: bool,
+}
+
+/// Comfy only tracks the mouse, touches are read from egui instead
+#[derive(Default)]
+pub struct TouchInput {
+    /// Set with the first touch, switches the UI to bigger buttons without keyboard hints
+    pub is_touch_device: bool,
+    press: Option<TouchPress>,
+    /// Tile described after a long press
+    info_tile: Option<TileMapPos>,
+}
+
+fn enlarge_ui_for_fingers() {
+    let mut style = (*egui().style()).clone();
+    style.spacing.interact_size.y = 40.;
+    style.spacing.button_padding = egui::vec2(12., 8.);
+    style.spacing.item_spacing = egui::vec2(10., 8.);
+    egui().set_style(style);
+}
+
+fn tile_at(tile_map: &TileMap, pos: egui::Pos2) -> Option<TileMapPos> {
+    let screen = Vec2::new(pos.x, pos.y) * egui().pixels_per_point();
+    tile_map.pos_from_absolute(screen_to_world(screen))
+}
+
+impl GameState {
+    /// Tap to select or build, long-press to describe a tile, pinching is left to the camera
+    pub fn touch_inputs(&mut self) {
+        let (any_touches, pressed, released, pos, secs, multi_touch) = egui().input(|input| {
+            (
+                input.any_touches(),
+                input.pointer.primary_pressed(),
+                input.pointer.primary_released(),
+                input.pointer.interact_pos(),
+                input.time,
+                input.multi_touch(),
+            )
+        });
+        if any_touches && !self.touch.is_touch_device {
+            self.touch.is_touch_device = true;
+            enlarge_ui_for_fingers();
+        }
+
+        if multi_touch.is_some() {
+            if let Some(press) = self.touch.press.as_mut() {
+                press.is_cancelled = true;
+            }
+        }
+
+        // Egui also reports mouse clicks as pointer presses, those are handled by `check_inputs`
+        if pressed && any_touches && !egui().wants_pointer_input() {
+            if let Some(start_pos) = pos {
+                self.touch.press = Some(TouchPress {
+                    start_pos,
+                    start_secs: secs,
+                    is_long: false,
+                    is_cancelled: false,
+                });
+            }
+        }
+        let Some(press) = self.touch.press.as_mut() else {
+            return;
+        };
+        if pos.is_some_and(|pos| pos.distance(press.start_pos) > TAP_SLOP) {
+            press.is_cancelled = true;
+        }
+        if !press.is_cancelled && !press.is_long && secs - press.start_secs >= LONG_PRESS_SECS {
+            press.is_long = true;
+            let tile = tile_at(&self.tile_map, press.start_pos);
+            if tile.is_some() {
+                self.selected_tile = tile;
+                self.touch.info_tile = tile;
+            }
+        }
+        if !released {
+            return;
+        }
+        let Some(press) = self.touch.press.take() else {
+            return;
+        };
+        if press.is_cancelled || press.is_long {
+            return;
+        }
+        let Some(tile) = tile_at(&self.tile_map, press.start_pos) else {
+            return;
+        };
+        match self.build_mode {
+            Some(build_mode) if !self.is_game_over => {
+                self.build_trap(tile, TrapBuilder::default_for(build_mode.trap_kind));
+            }
+            _ => self.selected_tile = Some(tile),
+        }
+    }
+
+    pub fn tile_info_ui(&mut self) {
+        let Some(tile) = self.touch.info_tile else {
+            return;
+        };
+        let mut is_open = true;
+        egui::Window::new("Tile info")
+            .anchor(Align2::CENTER_BOTTOM, [0., -80.])
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut is_open)
+            .show(egui(), |ui| {
+                ui.label(format!(
+                    "Terrain: {}",
+                    self.tile_map.terrain_at(tile).name()
+                ));
+                match self.trap_tiles.get(&tile) {
+                    Some(TrapTile::Built { trap, .. }) => {
+                        let source = DamageSource {
+                            tile,
+                            trap_kind: trap.kind(),
+                        };
+                        ui.heading(format!("{} trap", trap.kind().name()));
+                        self.trap_dps_ui(ui, source);
+                        self.upgrade_and_sell_ui(ui, tile);
+                    }
+                    _ if self.tile_map.is_buildable(tile) => {
+                        ui.label("Pick a trap below and tap here to build it");
+                    }
+                    _ => {
+                        ui.label("Traps cannot be built here");
+                    }
+                }
+            });
+        if !is_open {
+            self.touch.info_tile = None;
+        }
+    }
+}
@@ -1,46 +1,216 @@
 use comfy::*;
+use serde::{Deserialize, Serialize};
 
 use crate::*;
 
+pub const MAX_TRAP_UPGRADES: u32 = 3;
+/// How much stronger each upgrade makes a trap
+const UPGRADE_MULTIPLIER: f32 = 1.5;
+/// Part of the gold already spent on a trap its next upgrade costs
+const UPGRADE_COST_RATIO: f32 = 0.75;
+/// Part of the gold spent on a trap returned when selling it
+pub const SELL_REFUND_RATIO: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TrapKind {
+    Simple,
+    DamageOverTime,
+    Slow,
+    Wall,
+    Shooter,
+    AntiAir,
+}
+
+impl TrapKind {
+    pub const ALL: [TrapKind; 6] = [
+        TrapKind::Simple,
+        TrapKind::DamageOverTime,
+        TrapKind::Slow,
+        TrapKind::Wall,
+        TrapKind::Shooter,
+        TrapKind::AntiAir,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrapKind::Simple => "Simple",
+            TrapKind::DamageOverTime => "DOT",
+            TrapKind::Slow => "Slow",
+            TrapKind::Wall => "Wall",
+            TrapKind::Shooter => "Shooter",
+            TrapKind::AntiAir => "Anti-air",
+        }
+    }
+
+    pub fn sprite_name(&self) -> &'static str {
+        match self {
+            TrapKind::Simple => "traps/simple",
+            TrapKind::DamageOverTime => "traps/dot",
+            TrapKind::Slow => "traps/slow",
+            TrapKind::Wall => "traps/wall",
+            TrapKind::Shooter => "traps/shooter",
+            TrapKind::AntiAir => "traps/anti_air",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            TrapKind::Simple => BLUE,
+            TrapKind::DamageOverTime => PURPLE,
+            TrapKind::Slow => ORANGE,
+            TrapKind::Wall => DARKBROWN,
+            TrapKind::Shooter => DARKGREEN,
+            TrapKind::AntiAir => SKYBLUE,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Trap {
     Simple {
+        #[serde(with = "timer_serde")]
         cooldown: Timer,
         damage: f32,
     },
     DamageOverTime {
+        #[serde(with = "timer_serde")]
         cooldown: Timer,
         duration_secs: f32,
         damage_per_second: f32,
     },
     Slow {
+        #[serde(with = "timer_serde")]
         cooldown: Timer,
         duration_secs: f32,
         slow_effect: f32,
         area: u32,
     },
+    Wall,
+    Shooter {
+        #[serde(with = "timer_serde")]
+        cooldown: Timer,
+        damage: f32,
+        range: f32,
+    },
+    AntiAir {
+        #[serde(with = "timer_serde")]
+        cooldown: Timer,
+        damage: f32,
+        area: u32,
+    },
 }
 
 impl Trap {
+    pub fn kind(&self) -> TrapKind {
+        match self {
+            Trap::Simple { .. } => TrapKind::Simple,
+            Trap::DamageOverTime { .. } => TrapKind::DamageOverTime,
+            Trap::Slow { .. } => TrapKind::Slow,
+            Trap::Wall => TrapKind::Wall,
+            Trap::Shooter { .. } => TrapKind::Shooter,
+            Trap::AntiAir { .. } => TrapKind::AntiAir,
+        }
+    }
+
     pub fn draw(&self, tile_map_pos: TileMapPos) {
-        let color = match self {
-            Trap::Simple { .. } => BLUE,
-            Trap::DamageOverTime { .. } => PURPLE,
-            Trap::Slow { .. } => ORANGE,
+        // Frame 0 is idle, the others play once right after triggering
+        let frame = match self.secs_since_triggered() {
+            Some(secs) if secs < TRIGGER_ANIMATION_SECS => {
+                let animation_frames = sprite_frames(self.kind().sprite_name()).unwrap_or(1) - 1;
+                1 + (secs / TRIGGER_ANIMATION_SECS * animation_frames as f32) as u32
+            }
+            _ => 0,
         };
-        draw_circle(tile_map_pos.into_absolute_mid(), 0.3, color, 0)
+        if draw_sprite_frame(
+            self.kind().sprite_name(),
+            tile_map_pos.into_absolute_mid(),
+            Vec2::splat(0.9 * tile_map::TILE_SIZE),
+            WHITE,
+            0,
+            frame,
+        ) {
+            return;
+        }
+
+        let color = self.kind().color();
+        match self {
+            Trap::Wall => draw_rect(
+                tile_map_pos.into_absolute_mid(),
+                Vec2::splat(0.9 * tile_map::TILE_SIZE),
+                color,
+                0,
+            ),
+            _ => draw_circle(tile_map_pos.into_absolute_mid(), 0.3, color, 0),
+        }
     }
 
-    pub fn draw_activation_effect(tile_map_pos: TileMapPos, color: Color) {
-        draw_rect(
-            tile_map_pos.into_absolute_mid(),
-            Vec2::new(tile_map::TILE_SIZE, tile_map::TILE_SIZE),
-            color,
-            2,
-        )
+    fn secs_since_triggered(&self) -> Option<f32> {
+        match self {
+            Trap::Simple { cooldown, .. }
+            | Trap::DamageOverTime { cooldown, .. }
+            | Trap::Slow { cooldown, .. }
+            | Trap::Shooter { cooldown, .. }
+            | Trap::AntiAir { cooldown, .. } => Some(cooldown.elapsed_secs()),
+            Trap::Wall => None,
+        }
+    }
+
+    /// Walls only block the way, there is nothing to make stronger
+    pub fn can_upgrade(&self) -> bool {
+        !matches!(self, Trap::Wall)
+    }
+
+    fn upgrade(&mut self) {
+        match self {
+            Trap::Simple { damage, .. }
+            | Trap::Shooter { damage, .. }
+            | Trap::AntiAir { damage, .. } => *damage *= UPGRADE_MULTIPLIER,
+            Trap::DamageOverTime {
+                damage_per_second, ..
+            } => *damage_per_second *= UPGRADE_MULTIPLIER,
+            Trap::Slow { duration_secs, .. } => *duration_secs *= UPGRADE_MULTIPLIER,
+            Trap::Wall => {}
+        }
+    }
+
+    /// Walls cannot be walked through, enemies have to path around them
+    pub fn blocks_movement(&self) -> bool {
+        matches!(self, Trap::Wall)
+    }
+
+    /// Movement layers of the enemies the trap can hit
+    pub fn target_layers(&self) -> &'static [MovementLayer] {
+        match self {
+            Trap::Simple { .. } | Trap::DamageOverTime { .. } => &[MovementLayer::Ground],
+            Trap::Slow { .. } | Trap::Shooter { .. } => {
+                &[MovementLayer::Ground, MovementLayer::Air]
+            }
+            Trap::AntiAir { .. } => &[MovementLayer::Air],
+            Trap::Wall => &[],
+        }
+    }
+
+    /// Average damage per second dealt to an enemy standing on the trap's tile
+    pub fn damage_per_second(&self) -> f32 {
+        match self {
+            Trap::Simple { cooldown, damage } => damage / cooldown.duration().as_secs_f32(),
+            Trap::DamageOverTime {
+                cooldown,
+                duration_secs,
+                damage_per_second,
+            } => damage_per_second * duration_secs / cooldown.duration().as_secs_f32(),
+            Trap::Shooter {
+                cooldown, damage, ..
+            }
+            | Trap::AntiAir {
+                cooldown, damage, ..
+            } => damage / cooldown.duration().as_secs_f32(),
+            Trap::Slow { .. } | Trap::Wall => 0.,
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum TrapBuilder {
     Simple {
         cooldown_secs: f32,
@@ -60,6 +230,21 @@ pub enum TrapBuilder {
         area: u32,
         gold_cost: f32,
     },
+    Wall {
+        gold_cost: f32,
+    },
+    Shooter {
+        cooldown_secs: f32,
+        damage: f32,
+        range: f32,
+        gold_cost: f32,
+    },
+    AntiAir {
+        cooldown_secs: f32,
+        damage: f32,
+        area: u32,
+        gold_cost: f32,
+    },
 }
 
 impl TrapBuilder {
@@ -89,6 +274,28 @@ impl TrapBuilder {
             gold_cost: 30.,
         }
     }
+
+    pub fn default_wall() -> Self {
+        Self::Wall { gold_cost: 15. }
+    }
+
+    pub fn default_shooter() -> Self {
+        Self::Shooter {
+            cooldown_secs: 1.5,
+            damage: 4.,
+            range: 3.,
+            gold_cost: 40.,
+        }
+    }
+
+    pub fn default_anti_air() -> Self {
+        Self::AntiAir {
+            cooldown_secs: 1.,
+            damage: 4.,
+            area: 1,
+            gold_cost: 35.,
+        }
+    }
 }
 
 impl Default for TrapBuilder {
@@ -98,14 +305,34 @@ impl Default for TrapBuilder {
 }
 
 impl TrapBuilder {
-    pub fn name(&self) -> &'static str {
+    pub fn default_for(kind: TrapKind) -> Self {
+        match kind {
+            TrapKind::Simple => Self::default_simple(),
+            TrapKind::DamageOverTime => Self::default_damage_over_time(),
+            TrapKind::Slow => Self::default_slow(),
+            TrapKind::Wall => Self::default_wall(),
+            TrapKind::Shooter => Self::default_shooter(),
+            TrapKind::AntiAir => Self::default_anti_air(),
+        }
+    }
+}
+
+impl TrapBuilder {
+    pub fn kind(&self) -> TrapKind {
         match self {
-            TrapBuilder::Simple { .. } => "Simple",
-            TrapBuilder::DamageOverTime { .. } => "DOT",
-            TrapBuilder::Slow { .. } => "Slow",
+            TrapBuilder::Simple { .. } => TrapKind::Simple,
+            TrapBuilder::DamageOverTime { .. } => TrapKind::DamageOverTime,
+            TrapBuilder::Slow { .. } => TrapKind::Slow,
+            TrapBuilder::Wall { .. } => TrapKind::Wall,
+            TrapBuilder::Shooter { .. } => TrapKind::Shooter,
+            TrapBuilder::AntiAir { .. } => TrapKind::AntiAir,
         }
     }
 
+    pub fn name(&self) -> &'static str {
+        self.kind().name()
+    }
+
     pub fn into_trap(&self) -> Trap {
         match self {
             TrapBuilder::Simple {
@@ -138,10 +365,32 @@ impl TrapBuilder {
                 slow_effect: *slow_effect,
                 area: *area,
             },
+            TrapBuilder::Wall { gold_cost: _ } => Trap::Wall,
+            TrapBuilder::Shooter {
+                cooldown_secs,
+                damage,
+                range,
+                gold_cost: _,
+            } => Trap::Shooter {
+                cooldown: Timer::from_seconds(*cooldown_secs, true),
+                damage: *damage,
+                range: *range,
+            },
+            TrapBuilder::AntiAir {
+                cooldown_secs,
+                damage,
+                area,
+                gold_cost: _,
+            } => Trap::AntiAir {
+                cooldown: Timer::from_seconds(*cooldown_secs, true),
+                damage: *damage,
+                area: *area,
+            },
         }
     }
 
-    pub fn as_ui(&mut self, ui: &mut egui::Ui, player_gold: &mut f32) -> Option<Trap> {
+    /// Returns whether building was requested
+    pub fn as_ui(&mut self, ui: &mut egui::Ui, player_gold: f32) -> bool {
         egui::ComboBox::from_label("Choose a trap")
             .selected_text(self.name())
             .show_ui(ui, |ui| {
@@ -157,9 +406,24 @@ impl TrapBuilder {
                     TrapBuilder::Slow { .. } => *self,
                     _ => Self::default_slow(),
                 };
+                let default_wall = match self {
+                    TrapBuilder::Wall { .. } => *self,
+                    _ => Self::default_wall(),
+                };
+                let default_shooter = match self {
+                    TrapBuilder::Shooter { .. } => *self,
+                    _ => Self::default_shooter(),
+                };
+                let default_anti_air = match self {
+                    TrapBuilder::AntiAir { .. } => *self,
+                    _ => Self::default_anti_air(),
+                };
                 ui.selectable_value(self, default_simple, default_simple.name());
                 ui.selectable_value(self, default_dot, default_dot.name());
                 ui.selectable_value(self, default_slow, default_slow.name());
+                ui.selectable_value(self, default_wall, default_wall.name());
+                ui.selectable_value(self, default_shooter, default_shooter.name());
+                ui.selectable_value(self, default_anti_air, default_anti_air.name());
             });
         match self {
             TrapBuilder::Simple {
@@ -167,9 +431,9 @@ impl TrapBuilder {
                 damage,
                 gold_cost,
             } => {
-                ui.label(&format!("Damage: {}", damage));
-                ui.label(&format!("Cooldown (s): {}", cooldown_secs));
-                ui.label(&format!("Gold Cost: {}", gold_cost));
+                ui.label(format!("Damage: {}", damage));
+                ui.label(format!("Cooldown (s): {}", cooldown_secs));
+                ui.label(format!("Gold Cost: {}", gold_cost));
             }
             TrapBuilder::DamageOverTime {
                 cooldown_secs,
@@ -177,10 +441,10 @@ impl TrapBuilder {
                 damage_per_second,
                 gold_cost,
             } => {
-                ui.label(&format!("Damage per second: {}", damage_per_second));
-                ui.label(&format!("Cooldown (s): {}", cooldown_secs));
-                ui.label(&format!("Duration (s): {}", duration_secs));
-                ui.label(&format!("Gold Cost: {}", gold_cost));
+                ui.label(format!("Damage per second: {}", damage_per_second));
+                ui.label(format!("Cooldown (s): {}", cooldown_secs));
+                ui.label(format!("Duration (s): {}", duration_secs));
+                ui.label(format!("Gold Cost: {}", gold_cost));
             }
             TrapBuilder::Slow {
                 cooldown_secs,
@@ -189,34 +453,68 @@ impl TrapBuilder {
                 area,
                 gold_cost,
             } => {
-                ui.label(&format!("Slow effect: {}", slow_effect));
-                ui.label(&format!("Area: {}", area));
-                ui.label(&format!("Cooldown (s): {}", cooldown_secs));
-                ui.label(&format!("Duration (s): {}", duration_secs));
-                ui.label(&format!("Gold Cost: {}", gold_cost));
+                ui.label(format!("Slow effect: {}", slow_effect));
+                ui.label(format!("Area: {}", area));
+                ui.label(format!("Cooldown (s): {}", cooldown_secs));
+                ui.label(format!("Duration (s): {}", duration_secs));
+                ui.label(format!("Gold Cost: {}", gold_cost));
             }
-        }
-        if ui.button("Build").clicked() {
-            let gold_cost = match self {
-                TrapBuilder::Simple { gold_cost, .. }
-                | TrapBuilder::DamageOverTime { gold_cost, .. }
-                | TrapBuilder::Slow { gold_cost, .. } => *gold_cost,
-            };
-            if *player_gold >= gold_cost {
-                *player_gold -= gold_cost;
-                return Some(self.into_trap());
-            } else {
-                // TODO: Better reporting
-                println!("Not enough gold to build!");
-                return None;
+            TrapBuilder::Wall { gold_cost } => {
+                ui.label("Blocks the way, enemies have to walk around");
+                ui.label(format!("Gold Cost: {}", gold_cost));
+            }
+            TrapBuilder::Shooter {
+                cooldown_secs,
+                damage,
+                range,
+                gold_cost,
+            } => {
+                ui.label(format!("Damage: {}", damage));
+                ui.label(format!("Range: {}", range));
+                ui.label(format!("Cooldown (s): {}", cooldown_secs));
+                ui.label(format!("Gold Cost: {}", gold_cost));
+            }
+            TrapBuilder::AntiAir {
+                cooldown_secs,
+                damage,
+                area,
+                gold_cost,
+            } => {
+                ui.label(format!("Damage: {}", damage));
+                ui.label(format!("Area: {}", area));
+                ui.label(format!("Cooldown (s): {}", cooldown_secs));
+                ui.label(format!("Gold Cost: {}", gold_cost));
             }
         }
-        None
+        let missing_gold = self.gold_cost() - player_gold;
+        ui.add_enabled(missing_gold <= 0., egui::Button::new("Build"))
+            .on_disabled_hover_text(format!("Not enough gold, {:.0} more needed", missing_gold))
+            .clicked()
+    }
+
+    pub fn gold_cost(&self) -> f32 {
+        match self {
+            TrapBuilder::Simple { gold_cost, .. }
+            | TrapBuilder::DamageOverTime { gold_cost, .. }
+            | TrapBuilder::Slow { gold_cost, .. }
+            | TrapBuilder::Wall { gold_cost }
+            | TrapBuilder::Shooter { gold_cost, .. }
+            | TrapBuilder::AntiAir { gold_cost, .. } => *gold_cost,
+        }
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub enum TrapTile {
-    Built(Trap),
+    Built {
+        trap: Trap,
+        /// At most `MAX_TRAP_UPGRADES`
+        upgrades: u32,
+        /// Building and every upgrade, selling refunds a part of it
+        gold_spent: f32,
+        /// Triggers with an enemy in reach, a trap that hit once is no longer refunded in full
+        hits: u32,
+    },
     ToBeBuild(TrapBuilder),
 }
 
@@ -227,115 +525,190 @@ impl Default for TrapTile {
 }
 
 impl TrapTile {
-    pub fn debug_ui(&mut self, ui: &mut egui::Ui, player_gold: &mut f32) {
+    /// Returns the trap to build when building was requested,
+    /// built traps can only be tuned in sandbox mode
+    pub fn debug_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        player_gold: f32,
+        allow_cheats: bool,
+    ) -> Option<TrapBuilder> {
         match self {
-            TrapTile::Built(trap) => match trap {
-                Trap::Simple { cooldown, damage } => {
-                    ui.label("Simple");
-                    ui.horizontal(|ui| {
-                        ui.label("Damage:");
-                        ui.add(
-                            egui::DragValue::new(damage)
-                                .speed(1.0)
-                                .clamp_range(1. ..=100.),
-                        );
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Frequency (s):");
-                        let mut trigger_cooldown = cooldown.duration().as_secs_f32();
-                        ui.add(
-                            egui::DragValue::new(&mut trigger_cooldown)
-                                .speed(0.1)
-                                .clamp_range(0.1..=100.),
-                        );
-                        cooldown.set_duration(Duration::from_secs_f32(trigger_cooldown));
-                    });
-                }
-                Trap::DamageOverTime {
-                    cooldown,
-                    duration_secs,
-                    damage_per_second,
-                } => {
-                    ui.label("Damage over time");
-                    ui.horizontal(|ui| {
-                        ui.label("Damage per second:");
-                        ui.add(
-                            egui::DragValue::new(damage_per_second)
-                                .speed(1.0)
-                                .clamp_range(1. ..=100.),
-                        );
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Duration (s):");
-                        ui.add(
-                            egui::DragValue::new(duration_secs)
-                                .speed(1.0)
-                                .clamp_range(1. ..=100.),
-                        );
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Frequency (s):");
-                        let mut trigger_cooldown = cooldown.duration().as_secs_f32();
-                        ui.add(
-                            egui::DragValue::new(&mut trigger_cooldown)
-                                .speed(0.1)
-                                .clamp_range(0.1..=100.),
-                        );
-                        cooldown.set_duration(Duration::from_secs_f32(trigger_cooldown));
-                    });
-                }
-                Trap::Slow {
-                    cooldown,
-                    duration_secs,
-                    slow_effect,
-                    area,
-                } => {
-                    ui.label("Slow");
-                    ui.horizontal(|ui| {
-                        ui.label("Slow effect");
-                        ui.add(
-                            egui::DragValue::new(slow_effect)
-                                .speed(0.01)
-                                .clamp_range(0.01..=1.),
-                        );
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Area");
-                        ui.add(egui::DragValue::new(area).speed(1).clamp_range(0..=3));
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Duration (s):");
-                        ui.add(
-                            egui::DragValue::new(duration_secs)
-                                .speed(1.0)
-                                .clamp_range(1. ..=100.),
-                        );
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Frequency (s):");
-                        let mut trigger_cooldown = cooldown.duration().as_secs_f32();
-                        ui.add(
-                            egui::DragValue::new(&mut trigger_cooldown)
-                                .speed(0.1)
-                                .clamp_range(0.1..=100.),
-                        );
-                        cooldown.set_duration(Duration::from_secs_f32(trigger_cooldown));
-                    });
-                }
-            },
+            TrapTile::Built { trap, .. } => {
+                ui.add_enabled_ui(allow_cheats, |ui| match trap {
+                    Trap::Simple { cooldown, damage } => {
+                        ui.label("Simple");
+                        ui.horizontal(|ui| {
+                            ui.label("Damage:");
+                            ui.add(
+                                egui::DragValue::new(damage)
+                                    .speed(1.0)
+                                    .clamp_range(1. ..=100.),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Frequency (s):");
+                            let mut trigger_cooldown = cooldown.duration().as_secs_f32();
+                            ui.add(
+                                egui::DragValue::new(&mut trigger_cooldown)
+                                    .speed(0.1)
+                                    .clamp_range(0.1..=100.),
+                            );
+                            cooldown.set_duration(Duration::from_secs_f32(trigger_cooldown));
+                        });
+                    }
+                    Trap::DamageOverTime {
+                        cooldown,
+                        duration_secs,
+                        damage_per_second,
+                    } => {
+                        ui.label("Damage over time");
+                        ui.horizontal(|ui| {
+                            ui.label("Damage per second:");
+                            ui.add(
+                                egui::DragValue::new(damage_per_second)
+                                    .speed(1.0)
+                                    .clamp_range(1. ..=100.),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Duration (s):");
+                            ui.add(
+                                egui::DragValue::new(duration_secs)
+                                    .speed(1.0)
+                                    .clamp_range(1. ..=100.),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Frequency (s):");
+                            let mut trigger_cooldown = cooldown.duration().as_secs_f32();
+                            ui.add(
+                                egui::DragValue::new(&mut trigger_cooldown)
+                                    .speed(0.1)
+                                    .clamp_range(0.1..=100.),
+                            );
+                            cooldown.set_duration(Duration::from_secs_f32(trigger_cooldown));
+                        });
+                    }
+                    Trap::Slow {
+                        cooldown,
+                        duration_secs,
+                        slow_effect,
+                        area,
+                    } => {
+                        ui.label("Slow");
+                        ui.horizontal(|ui| {
+                            ui.label("Slow effect");
+                            ui.add(
+                                egui::DragValue::new(slow_effect)
+                                    .speed(0.01)
+                                    .clamp_range(0.01..=1.),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Area");
+                            ui.add(egui::DragValue::new(area).speed(1).clamp_range(0..=3));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Duration (s):");
+                            ui.add(
+                                egui::DragValue::new(duration_secs)
+                                    .speed(1.0)
+                                    .clamp_range(1. ..=100.),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Frequency (s):");
+                            let mut trigger_cooldown = cooldown.duration().as_secs_f32();
+                            ui.add(
+                                egui::DragValue::new(&mut trigger_cooldown)
+                                    .speed(0.1)
+                                    .clamp_range(0.1..=100.),
+                            );
+                            cooldown.set_duration(Duration::from_secs_f32(trigger_cooldown));
+                        });
+                    }
+                    Trap::Wall => {
+                        ui.label("Wall");
+                    }
+                    Trap::Shooter {
+                        cooldown,
+                        damage,
+                        range,
+                    } => {
+                        ui.label("Shooter");
+                        ui.horizontal(|ui| {
+                            ui.label("Damage:");
+                            ui.add(
+                                egui::DragValue::new(damage)
+                                    .speed(1.0)
+                                    .clamp_range(1. ..=100.),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Range:");
+                            ui.add(
+                                egui::DragValue::new(range)
+                                    .speed(0.1)
+                                    .clamp_range(0.5..=10.),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Frequency (s):");
+                            let mut trigger_cooldown = cooldown.duration().as_secs_f32();
+                            ui.add(
+                                egui::DragValue::new(&mut trigger_cooldown)
+                                    .speed(0.1)
+                                    .clamp_range(0.1..=100.),
+                            );
+                            cooldown.set_duration(Duration::from_secs_f32(trigger_cooldown));
+                        });
+                    }
+                    Trap::AntiAir {
+                        cooldown,
+                        damage,
+                        area,
+                    } => {
+                        ui.label("Anti-air");
+                        ui.horizontal(|ui| {
+                            ui.label("Damage:");
+                            ui.add(
+                                egui::DragValue::new(damage)
+                                    .speed(1.0)
+                                    .clamp_range(1. ..=100.),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Area");
+                            ui.add(egui::DragValue::new(area).speed(1).clamp_range(0..=3));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Frequency (s):");
+                            let mut trigger_cooldown = cooldown.duration().as_secs_f32();
+                            ui.add(
+                                egui::DragValue::new(&mut trigger_cooldown)
+                                    .speed(0.1)
+                                    .clamp_range(0.1..=100.),
+                            );
+                            cooldown.set_duration(Duration::from_secs_f32(trigger_cooldown));
+                        });
+                    }
+                });
+            }
             TrapTile::ToBeBuild(trap_builder) => {
-                if let Some(trap) = trap_builder.as_ui(ui, player_gold) {
-                    *self = TrapTile::Built(trap);
+                if trap_builder.as_ui(ui, player_gold) {
+                    return Some(*trap_builder);
                 }
             }
         }
+        None
     }
 }
 
 impl GameState {
     pub fn draw_traps(&self) {
         for (tile_map_pos, trap_tile) in self.trap_tiles.iter() {
-            if let TrapTile::Built(trap) = trap_tile {
+            if let TrapTile::Built { trap, .. } = trap_tile {
                 trap.draw(*tile_map_pos)
             }
         }
@@ -347,18 +720,44 @@ impl GameState {
         }
 
         for (tile_map_pos, trap_tile) in self.trap_tiles.iter_mut() {
-            if let TrapTile::Built(trap) = trap_tile {
+            if let TrapTile::Built { trap, hits, .. } = trap_tile {
+                let target_layers = trap.target_layers();
+                let is_target = |enemy: &Enemy| target_layers.contains(&enemy.archetype.layer());
+                let terrain_bonus = self
+                    .tile_map
+                    .terrain_at(*tile_map_pos)
+                    .trap_multiplier(trap.kind());
+                let source = DamageSource {
+                    tile: *tile_map_pos,
+                    trap_kind: trap.kind(),
+                };
                 match trap {
                     Trap::Simple { cooldown, damage } => {
                         cooldown.tick_secs(self.delta);
                         if cooldown.just_finished() {
-                            Trap::draw_activation_effect(*tile_map_pos, RED);
+                            let mut targets_hit = 0;
+                            self.effects.pulse(*tile_map_pos, RED);
                             // TODO: Potentially remake this to be more efficient using a hashmap of positions to list of enemies
                             self.enemies.iter_mut().for_each(|enemy| {
-                                if enemy.on_tiles().contains(tile_map_pos) {
-                                    enemy.hp.take_damage(*damage)
+                                if is_target(enemy)
+                                    && enemy.on_tiles(&self.tile_map).contains(tile_map_pos)
+                                {
+                                    targets_hit += 1;
+                                    let dealt = enemy.take_damage(*damage * terrain_bonus, source);
+                                    self.event_bus.emit(GameEvent::DamageDealt {
+                                        source,
+                                        damage: dealt,
+                                        position: enemy.position,
+                                    });
                                 }
                             });
+                            if targets_hit > 0 {
+                                *hits += 1;
+                            }
+                            self.event_bus.emit(GameEvent::TrapTriggered {
+                                source,
+                                targets_hit,
+                            });
                         }
                     }
                     Trap::DamageOverTime {
@@ -368,16 +767,28 @@ impl GameState {
                     } => {
                         cooldown.tick_secs(self.delta);
                         if cooldown.just_finished() {
-                            Trap::draw_activation_effect(*tile_map_pos, YELLOW);
+                            let mut targets_hit = 0;
+                            self.effects.pulse(*tile_map_pos, YELLOW);
                             // TODO: Potentially remake this to be more efficient using a hashmap of positions to list of enemies
                             self.enemies.iter_mut().for_each(|enemy| {
-                                if enemy.on_tiles().contains(tile_map_pos) {
+                                if is_target(enemy)
+                                    && enemy.on_tiles(&self.tile_map).contains(tile_map_pos)
+                                {
+                                    targets_hit += 1;
                                     enemy.damage_over_time_effects.push(DamageOverTimeEffect {
                                         timer: Timer::from_seconds(*duration_secs, false),
-                                        damage_per_second: *damage_per_second,
+                                        damage_per_second: *damage_per_second * terrain_bonus,
+                                        source,
                                     })
                                 }
                             });
+                            if targets_hit > 0 {
+                                *hits += 1;
+                            }
+                            self.event_bus.emit(GameEvent::TrapTriggered {
+                                source,
+                                targets_hit,
+                            });
                         }
                     }
                     Trap::Slow {
@@ -388,22 +799,110 @@ impl GameState {
                     } => {
                         cooldown.tick_secs(self.delta);
                         if cooldown.just_finished() {
-                            let affected_tiles = tile_map_pos.area_til_distance(*area);
-                            for tile in affected_tiles.iter() {
-                                Trap::draw_activation_effect(*tile, BLUE);
-                            }
+                            let mut targets_hit = 0;
+                            let affected_tiles =
+                                self.tile_map.area_til_distance(*tile_map_pos, *area);
+                            self.effects.ring(
+                                tile_map_pos.into_absolute_mid(),
+                                (*area as f32 + 0.5) * tile_map::TILE_SIZE,
+                                BLUE,
+                            );
                             self.enemies.iter_mut().for_each(|enemy| {
-                                if enemy
-                                    .on_tiles()
-                                    .iter()
-                                    .any(|tile| affected_tiles.contains(tile))
+                                if is_target(enemy)
+                                    && enemy
+                                        .on_tiles(&self.tile_map)
+                                        .iter()
+                                        .any(|tile| affected_tiles.contains(tile))
                                 {
+                                    targets_hit += 1;
                                     enemy.slow_effects.push(SlowEffect {
                                         timer: Timer::from_seconds(*duration_secs, false),
-                                        strength: *slow_effect,
+                                        strength: *slow_effect * terrain_bonus,
                                     })
                                 }
-                            })
+                            });
+                            if targets_hit > 0 {
+                                *hits += 1;
+                            }
+                            self.event_bus.emit(GameEvent::TrapTriggered {
+                                source,
+                                targets_hit,
+                            });
+                        }
+                    }
+                    Trap::Wall => {}
+                    Trap::Shooter {
+                        cooldown,
+                        damage,
+                        range,
+                    } => {
+                        cooldown.tick_secs(self.delta);
+                        if cooldown.just_finished() {
+                            let mut targets_hit = 0;
+                            let origin = tile_map_pos.into_absolute_mid();
+                            let target = self
+                                .enemies
+                                .iter_mut()
+                                .filter(|enemy| is_target(enemy))
+                                .filter(|enemy| enemy.position.distance(origin) <= *range)
+                                .min_by_key(|enemy| OrderedFloat(enemy.position.distance(origin)));
+                            if let Some(enemy) = target {
+                                targets_hit += 1;
+                                self.effects.beam(origin, enemy.position, DARKGREEN);
+                                let dealt = enemy.take_damage(*damage * terrain_bonus, source);
+                                self.event_bus.emit(GameEvent::DamageDealt {
+                                    source,
+                                    damage: dealt,
+                                    position: enemy.position,
+                                });
+                            }
+                            if targets_hit > 0 {
+                                *hits += 1;
+                            }
+                            self.event_bus.emit(GameEvent::TrapTriggered {
+                                source,
+                                targets_hit,
+                            });
+                        }
+                    }
+                    Trap::AntiAir {
+                        cooldown,
+                        damage,
+                        area,
+                    } => {
+                        cooldown.tick_secs(self.delta);
+                        if cooldown.just_finished() {
+                            let mut targets_hit = 0;
+                            let affected_tiles =
+                                self.tile_map.area_til_distance(*tile_map_pos, *area);
+                            self.effects.ring(
+                                tile_map_pos.into_absolute_mid(),
+                                (*area as f32 + 0.5) * tile_map::TILE_SIZE,
+                                SKYBLUE,
+                            );
+                            self.enemies.iter_mut().for_each(|enemy| {
+                                if is_target(enemy)
+                                    && enemy
+                                        .on_tiles(&self.tile_map)
+                                        .iter()
+                                        .any(|tile| affected_tiles.contains(tile))
+                                {
+                                    targets_hit += 1;
+                                    let dealt = enemy.take_damage(*damage * terrain_bonus, source);
+                                    self.event_bus.emit(GameEvent::DamageDealt {
+                                        source,
+                                        damage: dealt,
+                                        position: enemy.position,
+                                    });
+                                }
+                            });
+                            if targets_hit > 0 {
+                                *hits += 1;
+                            }
+                            self.event_bus.emit(GameEvent::TrapTriggered {
+                                source,
+                                targets_hit,
+                            });
                         }
                     }
                 }
@@ -416,13 +915,254 @@ impl GameState {
             None => {
                 ui.heading("No tile selected");
             }
+            Some(tile_map_pos) if !self.tile_map.is_buildable(tile_map_pos) => {
+                ui.heading(format!(
+                    "Cannot build on ({},{})",
+                    tile_map_pos.x, tile_map_pos.y
+                ));
+            }
             Some(tile_map_pos) => {
-                ui.heading(&format!("Trap on ({},{})", tile_map_pos.x, tile_map_pos.y));
-                self.trap_tiles
-                    .entry(tile_map_pos)
-                    .or_default()
-                    .debug_ui(ui, &mut self.player.gold.value);
+                ui.heading(format!("Trap on ({},{})", tile_map_pos.x, tile_map_pos.y));
+                ui.label(format!(
+                    "Terrain: {}",
+                    self.tile_map.terrain_at(tile_map_pos).name()
+                ));
+                let allow_cheats = self.mode == GameMode::Sandbox;
+                let trap_tile = self.trap_tiles.entry(tile_map_pos).or_default();
+                if let Some(trap_builder) =
+                    trap_tile.debug_ui(ui, self.player.gold.value, allow_cheats)
+                {
+                    self.build_trap(tile_map_pos, trap_builder);
+                }
+                self.upgrade_and_sell_ui(ui, tile_map_pos);
+                // Tuned damage changes which paths are dangerous
+                if allow_cheats {
+                    self.distance_fields.invalidate();
+                }
+                if let Some(TrapTile::Built { trap, .. }) = self.trap_tiles.get(&tile_map_pos) {
+                    let source = DamageSource {
+                        tile: tile_map_pos,
+                        trap_kind: trap.kind(),
+                    };
+                    ui.separator();
+                    self.trap_dps_ui(ui, source);
+                }
+            }
+        }
+    }
+
+    pub fn can_build_on(&self, tile_map_pos: TileMapPos) -> bool {
+        self.tile_map.is_buildable(tile_map_pos)
+            && !matches!(
+                self.trap_tiles.get(&tile_map_pos),
+                Some(TrapTile::Built { .. })
+            )
+    }
+
+    /// Pays for and builds a trap, returns whether it was built
+    pub fn build_trap(&mut self, tile_map_pos: TileMapPos, trap_builder: TrapBuilder) -> bool {
+        if !self.can_build_on(tile_map_pos) {
+            return false;
+        }
+        let trap = trap_builder.into_trap();
+        if trap.blocks_movement() && !self.keeps_paths_open(tile_map_pos) {
+            self.toasts.warning(BLOCKED_PATH_WARNING);
+            return false;
+        }
+        let gold_spent = trap_builder.gold_cost();
+        if self.player.gold.value < gold_spent {
+            self.toasts.warning(format!(
+                "Not enough gold to build {}, {:.0} more needed",
+                trap_builder.name(),
+                gold_spent - self.player.gold.value
+            ));
+            return false;
+        }
+        self.player.gold.value -= gold_spent;
+        let source = DamageSource {
+            tile: tile_map_pos,
+            trap_kind: trap.kind(),
+        };
+        let previous = self.trap_tiles.insert(
+            tile_map_pos,
+            TrapTile::Built {
+                trap,
+                upgrades: 0,
+                gold_spent,
+                hits: 0,
+            },
+        );
+        self.distance_fields.invalidate();
+        self.undo_history.record(
+            tile_map_pos,
+            TrapActionKind::Build,
+            gold_spent,
+            previous,
+            self.run_length_seconds,
+            self.is_paused,
+        );
+        self.event_bus
+            .emit(GameEvent::TrapBuilt { source, gold_spent });
+        self.event_bus.emit(GameEvent::GoldChanged {
+            amount: -gold_spent,
+            reason: GoldChangeReason::TrapBuilt,
+        });
+        true
+    }
+    /// Gold the next upgrade costs, `None` when there is no trap left to upgrade
+    pub fn upgrade_cost(&self, tile_map_pos: TileMapPos) -> Option<f32> {
+        match self.trap_tiles.get(&tile_map_pos) {
+            Some(TrapTile::Built {
+                trap,
+                upgrades,
+                gold_spent,
+                ..
+            }) if trap.can_upgrade() && *upgrades < MAX_TRAP_UPGRADES => {
+                Some(gold_spent * UPGRADE_COST_RATIO)
             }
+            _ => None,
         }
     }
+
+    /// Pays for and upgrades a built trap, returns whether it was upgraded
+    pub fn upgrade_trap(&mut self, tile_map_pos: TileMapPos) -> bool {
+        let Some(gold_spent) = self.upgrade_cost(tile_map_pos) else {
+            return false;
+        };
+        if self.player.gold.value < gold_spent {
+            self.toasts.warning(format!(
+                "Not enough gold to upgrade, {:.0} more needed",
+                gold_spent - self.player.gold.value
+            ));
+            return false;
+        }
+        let previous = self.trap_tiles.get(&tile_map_pos).cloned();
+        let Some(TrapTile::Built {
+            trap,
+            upgrades,
+            gold_spent: trap_gold_spent,
+            ..
+        }) = self.trap_tiles.get_mut(&tile_map_pos)
+        else {
+            return false;
+        };
+        trap.upgrade();
+        *upgrades += 1;
+        *trap_gold_spent += gold_spent;
+        let source = DamageSource {
+            tile: tile_map_pos,
+            trap_kind: trap.kind(),
+        };
+        self.player.gold.value -= gold_spent;
+        self.distance_fields.invalidate();
+        self.undo_history.record(
+            tile_map_pos,
+            TrapActionKind::Upgrade,
+            gold_spent,
+            previous,
+            self.run_length_seconds,
+            self.is_paused,
+        );
+        self.event_bus
+            .emit(GameEvent::TrapUpgraded { source, gold_spent });
+        self.event_bus.emit(GameEvent::GoldChanged {
+            amount: -gold_spent,
+            reason: GoldChangeReason::TrapUpgraded,
+        });
+        true
+    }
+
+    /// Gold selling returns, `None` when there is no trap to sell
+    pub fn sell_refund(&self, tile_map_pos: TileMapPos) -> Option<f32> {
+        match self.trap_tiles.get(&tile_map_pos) {
+            Some(TrapTile::Built { gold_spent, .. }) => Some(gold_spent * SELL_REFUND_RATIO),
+            _ => None,
+        }
+    }
+
+    /// Removes a built trap for a part of the gold spent on it, returns whether it was sold
+    pub fn sell_trap(&mut self, tile_map_pos: TileMapPos) -> bool {
+        let Some(gold_refunded) = self.sell_refund(tile_map_pos) else {
+            return false;
+        };
+        let Some(TrapTile::Built { trap, .. }) = self.trap_tiles.get(&tile_map_pos) else {
+            return false;
+        };
+        let source = DamageSource {
+            tile: tile_map_pos,
+            trap_kind: trap.kind(),
+        };
+        let previous = self.trap_tiles.insert(
+            tile_map_pos,
+            TrapTile::ToBeBuild(TrapBuilder::default_for(source.trap_kind)),
+        );
+        self.player.gold.value += gold_refunded;
+        self.distance_fields.invalidate();
+        self.undo_history.record(
+            tile_map_pos,
+            TrapActionKind::Sell,
+            -gold_refunded,
+            previous,
+            self.run_length_seconds,
+            self.is_paused,
+        );
+        self.event_bus.emit(GameEvent::TrapSold {
+            source,
+            gold_refunded,
+        });
+        self.event_bus.emit(GameEvent::GoldChanged {
+            amount: gold_refunded,
+            reason: GoldChangeReason::TrapSold,
+        });
+        true
+    }
+
+    pub fn upgrade_and_sell_inputs(&mut self) {
+        let Some(selected_tile) = self.selected_tile else {
+            return;
+        };
+        if self.keybindings.is_pressed(Action::UpgradeTrap) {
+            self.upgrade_trap(selected_tile);
+        } else if self.keybindings.is_pressed(Action::SellTrap) {
+            self.sell_trap(selected_tile);
+        }
+    }
+
+    /// Upgrade and sell buttons for the trap built on the tile, if any
+    pub fn upgrade_and_sell_ui(&mut self, ui: &mut egui::Ui, tile_map_pos: TileMapPos) {
+        let Some(TrapTile::Built { trap, upgrades, .. }) = self.trap_tiles.get(&tile_map_pos)
+        else {
+            return;
+        };
+        if trap.can_upgrade() {
+            ui.label(format!("Upgrades: {}/{}", upgrades, MAX_TRAP_UPGRADES));
+        }
+        ui.horizontal(|ui| {
+            if let Some(gold_cost) = self.upgrade_cost(tile_map_pos) {
+                let missing_gold = gold_cost - self.player.gold.value;
+                let text = self.with_key_hint(
+                    &format!("Upgrade for {:.0} gold", gold_cost),
+                    Action::UpgradeTrap,
+                );
+                let upgrade = ui
+                    .add_enabled(missing_gold <= 0., egui::Button::new(text))
+                    .on_disabled_hover_text(format!(
+                        "Not enough gold, {:.0} more needed",
+                        missing_gold
+                    ));
+                if upgrade.clicked() {
+                    self.upgrade_trap(tile_map_pos);
+                }
+            }
+            if let Some(gold_refunded) = self.sell_refund(tile_map_pos) {
+                let text = self.with_key_hint(
+                    &format!("Sell for {:.0} gold", gold_refunded),
+                    Action::SellTrap,
+                );
+                if ui.button(text).clicked() {
+                    self.sell_trap(tile_map_pos);
+                }
+            }
+        });
+    }
 }
@@ -0,0 +1,397 @@
+use comfy::*;
+
+use crate::*;
+
+/// While the game runs, actions can only be undone this soon after they were performed,
+/// actions performed while paused can be undone until the game is resumed
+const UNDO_GRACE_SECS: f32 = 3.;
+const MAX_UNDO_ACTIONS: usize = 50;
+
+/// What an undoable action did to its tile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapActionKind {
+    Build,
+    Upgrade,
+    Sell,
+}
+
+/// A trap being built, upgraded or sold, which can be undone or redone
+pub struct TrapAction {
+    tile: TileMapPos,
+    kind: TrapActionKind,
+    /// Gold the action costs when redone and returns when undone, negative for selling
+    gold_spent: f32,
+    /// What the tile held on the other side of the action: before the action
+    /// when it is waiting to be undone, after it when waiting to be redone
+    other_tile_state: Option<TrapTile>,
+    /// Run time of the last time the action was performed, undone or redone
+    performed_secs: f32,
+    /// Whether that happened while paused, cleared once the game is resumed
+    performed_while_paused: bool,
+}
+
+/// Reset with every run
+#[derive(Default)]
+pub struct UndoHistory {
+    undo: Vec<TrapAction>,
+    redo: Vec<TrapAction>,
+}
+
+impl UndoHistory {
+    pub fn record(
+        &mut self,
+        tile: TileMapPos,
+        kind: TrapActionKind,
+        gold_spent: f32,
+        previous_tile_state: Option<TrapTile>,
+        performed_secs: f32,
+        performed_while_paused: bool,
+    ) {
+        self.redo.clear();
+        self.undo.push(TrapAction {
+            tile,
+            kind,
+            gold_spent,
+            other_tile_state: previous_tile_state,
+            performed_secs,
+            performed_while_paused,
+        });
+        if self.undo.len() > MAX_UNDO_ACTIONS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Called while the game runs, so only the grace period is left for undoing
+    pub fn expire_paused_actions(&mut self) {
+        for action in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            action.performed_while_paused = false;
+        }
+    }
+}
+
+/// Enemies hit by a trap since it was in the given state
+fn hits_since(trap_tile: Option<&TrapTile>, earlier: Option<&TrapTile>) -> u32 {
+    let hits = |trap_tile: Option<&TrapTile>| match trap_tile {
+        Some(TrapTile::Built { hits, .. }) => *hits,
+        _ => 0,
+    };
+    hits(trap_tile).saturating_sub(hits(earlier))
+}
+
+impl GameState {
+    fn is_within_undo_grace(&self, action: &TrapAction) -> bool {
+        action.performed_while_paused
+            || self.run_length_seconds - action.performed_secs <= UNDO_GRACE_SECS
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.undo_history
+            .undo
+            .last()
+            .is_some_and(|action| self.is_within_undo_grace(action))
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.undo_history
+            .redo
+            .last()
+            .is_some_and(|action| self.is_within_undo_grace(action))
+    }
+
+    /// Restores the tile from before the last action and its gold, a trap which
+    /// already hit enemies since is only refunded as much as selling it would
+    pub fn undo(&mut self) {
+        if !self.can_undo() {
+            return;
+        }
+        let Some(action) = self.undo_history.undo.last() else {
+            return;
+        };
+        if !self.keeps_paths_open_with(action.tile, action.other_tile_state.as_ref()) {
+            self.toasts.warning(BLOCKED_PATH_WARNING);
+            return;
+        }
+        let gold_returned = match action.gold_spent > 0.
+            && hits_since(
+                self.trap_tiles.get(&action.tile),
+                action.other_tile_state.as_ref(),
+            ) > 0
+        {
+            true => action.gold_spent * SELL_REFUND_RATIO,
+            false => action.gold_spent,
+        };
+        if self.player.gold.value < -gold_returned {
+            self.toasts.warning(format!(
+                "Not enough gold to undo, {:.0} more needed",
+                -gold_returned - self.player.gold.value
+            ));
+            return;
+        }
+        let Some(mut action) = self.undo_history.undo.pop() else {
+            return;
+        };
+        if gold_returned < action.gold_spent {
+            self.toasts.info(format!(
+                "The trap already hit enemies, only {:.0} gold refunded",
+                gold_returned
+            ));
+        }
+        let undone = self.replace_tile_state(action.tile, action.other_tile_state.take());
+        self.player.gold.value += gold_returned;
+        if let Some(source) = self.action_source(action.tile, undone.as_ref()) {
+            let event = match action.kind {
+                TrapActionKind::Build => GameEvent::TrapRemoved {
+                    source,
+                    gold_refunded: gold_returned,
+                },
+                TrapActionKind::Upgrade => GameEvent::TrapUpgraded {
+                    source,
+                    gold_spent: -gold_returned,
+                },
+                TrapActionKind::Sell => GameEvent::TrapSold {
+                    source,
+                    gold_refunded: gold_returned,
+                },
+            };
+            self.event_bus.emit(event);
+            self.event_bus.emit(GameEvent::GoldChanged {
+                amount: gold_returned,
+                reason: GoldChangeReason::Refund,
+            });
+        }
+        action.gold_spent = gold_returned;
+        action.other_tile_state = undone;
+        action.performed_secs = self.run_length_seconds;
+        action.performed_while_paused = self.is_paused;
+        self.undo_history.redo.push(action);
+    }
+
+    /// Performs the last undone action again, leaving the tile exactly as it was when undone
+    pub fn redo(&mut self) {
+        if !self.can_redo() {
+            return;
+        }
+        let Some(action) = self.undo_history.redo.last() else {
+            return;
+        };
+        let (tile, gold_spent) = (action.tile, action.gold_spent);
+        if !self.keeps_paths_open_with(tile, action.other_tile_state.as_ref()) {
+            self.toasts.warning(BLOCKED_PATH_WARNING);
+            return;
+        }
+        if self.player.gold.value < gold_spent {
+            self.toasts.warning(format!(
+                "Not enough gold to redo, {:.0} more needed",
+                gold_spent - self.player.gold.value
+            ));
+            return;
+        }
+        let Some(mut action) = self.undo_history.redo.pop() else {
+            return;
+        };
+        let previous = self.replace_tile_state(action.tile, action.other_tile_state.take());
+        self.player.gold.value -= gold_spent;
+        if let Some(source) = self.action_source(action.tile, previous.as_ref()) {
+            let (event, reason) = match action.kind {
+                TrapActionKind::Build => (
+                    GameEvent::TrapBuilt { source, gold_spent },
+                    GoldChangeReason::TrapBuilt,
+                ),
+                TrapActionKind::Upgrade => (
+                    GameEvent::TrapUpgraded { source, gold_spent },
+                    GoldChangeReason::TrapUpgraded,
+                ),
+                TrapActionKind::Sell => (
+                    GameEvent::TrapSold {
+                        source,
+                        gold_refunded: -gold_spent,
+                    },
+                    GoldChangeReason::TrapSold,
+                ),
+            };
+            self.event_bus.emit(event);
+            self.event_bus.emit(GameEvent::GoldChanged {
+                amount: -gold_spent,
+                reason,
+            });
+        }
+        action.other_tile_state = previous;
+        action.performed_secs = self.run_length_seconds;
+        action.performed_while_paused = self.is_paused;
+        self.undo_history.undo.push(action);
+    }
+
+    /// Whether putting the given state on the tile keeps a way to the exit open
+    fn keeps_paths_open_with(&self, tile: TileMapPos, tile_state: Option<&TrapTile>) -> bool {
+        let is_wall = |tile_state: Option<&TrapTile>| matches!(tile_state, Some(TrapTile::Built { trap, .. }) if trap.blocks_movement());
+        !is_wall(tile_state) || is_wall(self.trap_tiles.get(&tile)) || self.keeps_paths_open(tile)
+    }
+
+    /// The trap an action was about, built on one side of it
+    fn action_source(
+        &self,
+        tile: TileMapPos,
+        other_tile_state: Option<&TrapTile>,
+    ) -> Option<DamageSource> {
+        let trap_kind = [self.trap_tiles.get(&tile), other_tile_state]
+            .into_iter()
+            .find_map(|trap_tile| match trap_tile {
+                Some(TrapTile::Built { trap, .. }) => Some(trap.kind()),
+                _ => None,
+            })?;
+        Some(DamageSource { tile, trap_kind })
+    }
+
+    fn replace_tile_state(
+        &mut self,
+        tile: TileMapPos,
+        tile_state: Option<TrapTile>,
+    ) -> Option<TrapTile> {
+        self.distance_fields.invalidate();
+        match tile_state {
+            Some(trap_tile) => self.trap_tiles.insert(tile, trap_tile),
+            None => self.trap_tiles.remove(&tile),
+        }
+    }
+
+    pub fn undo_inputs(&mut self) {
+        if self.keybindings.is_pressed(Action::Redo) {
+            self.redo();
+        } else if self.keybindings.is_pressed(Action::Undo) {
+            self.undo();
+        }
+    }
+
+    pub fn undo_ui(&mut self, ui: &mut egui::Ui) {
+        let undo_text = self.with_key_hint("Undo", Action::Undo);
+        let undo = ui.add_enabled(self.can_undo(), egui::Button::new(undo_text));
+        if with_grace_hint(undo, &self.undo_history.undo).clicked() {
+            self.undo();
+        }
+        let redo_text = self.with_key_hint("Redo", Action::Redo);
+        let redo = ui.add_enabled(self.can_redo(), egui::Button::new(redo_text));
+        if with_grace_hint(redo, &self.undo_history.redo).clicked() {
+            self.redo();
+        }
+    }
+}
+
+/// Explains why a button is disabled even though there is something to undo or redo
+fn with_grace_hint(response: egui::Response, actions: &[TrapAction]) -> egui::Response {
+    match actions.is_empty() {
+        true => response,
+        false => response.on_disabled_hover_text(
+            "Only possible while paused or right after building, upgrading or selling",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOLD: f32 = 100.;
+
+    fn state_with_trap() -> (GameState, TileMapPos) {
+        let mut state = GameState::default();
+        state.player.gold.value = GOLD;
+        let tile = (0..state.tile_map.columns)
+            .cartesian_product(0..state.tile_map.rows)
+            .map(|(x, y)| TileMapPos::new(x, y))
+            .find(|tile| state.tile_map.is_buildable(*tile))
+            .unwrap();
+        assert!(state.build_trap(tile, TrapBuilder::default_simple()));
+        (state, tile)
+    }
+
+    fn gold_cost() -> f32 {
+        TrapBuilder::default_simple().gold_cost()
+    }
+
+    fn is_built(state: &GameState, tile: TileMapPos) -> bool {
+        matches!(state.trap_tiles.get(&tile), Some(TrapTile::Built { .. }))
+    }
+
+    #[test]
+    fn undo_and_redo_building() {
+        let (mut state, tile) = state_with_trap();
+        assert_eq!(state.player.gold.value, GOLD - gold_cost());
+
+        state.undo();
+        assert!(!is_built(&state, tile));
+        assert_eq!(state.player.gold.value, GOLD);
+
+        state.redo();
+        assert!(is_built(&state, tile));
+        assert_eq!(state.player.gold.value, GOLD - gold_cost());
+    }
+
+    #[test]
+    fn traps_which_hit_enemies_are_refunded_like_selling() {
+        let (mut state, tile) = state_with_trap();
+        if let Some(TrapTile::Built { hits, .. }) = state.trap_tiles.get_mut(&tile) {
+            *hits += 1;
+        }
+        let refund = gold_cost() * SELL_REFUND_RATIO;
+
+        state.undo();
+        assert_eq!(state.player.gold.value, GOLD - gold_cost() + refund);
+        // Redoing costs only what was refunded
+        state.redo();
+        assert_eq!(state.player.gold.value, GOLD - gold_cost());
+    }
+
+    #[test]
+    fn undo_upgrading_and_selling() {
+        let (mut state, tile) = state_with_trap();
+        let upgrade_cost = state.upgrade_cost(tile).unwrap();
+        assert!(state.upgrade_trap(tile));
+        let refund = state.sell_refund(tile).unwrap();
+        assert!(state.sell_trap(tile));
+        assert_eq!(
+            state.player.gold.value,
+            GOLD - gold_cost() - upgrade_cost + refund
+        );
+
+        state.undo();
+        assert!(is_built(&state, tile));
+        assert_eq!(state.player.gold.value, GOLD - gold_cost() - upgrade_cost);
+
+        state.undo();
+        assert!(matches!(
+            state.trap_tiles.get(&tile),
+            Some(TrapTile::Built { upgrades: 0, .. })
+        ));
+        assert_eq!(state.player.gold.value, GOLD - gold_cost());
+
+        state.redo();
+        state.redo();
+        assert!(!is_built(&state, tile));
+        assert_eq!(
+            state.player.gold.value,
+            GOLD - gold_cost() - upgrade_cost + refund
+        );
+    }
+
+    #[test]
+    fn undo_grace_ends_after_a_while_or_once_resumed() {
+        let (mut state, _) = state_with_trap();
+        state.run_length_seconds += UNDO_GRACE_SECS + 1.;
+        assert!(!state.can_undo());
+
+        state.is_paused = true;
+        state.undo_history.record(
+            TileMapPos::new(0, 0),
+            TrapActionKind::Build,
+            gold_cost(),
+            None,
+            state.run_length_seconds,
+            state.is_paused,
+        );
+        state.run_length_seconds += UNDO_GRACE_SECS + 1.;
+        assert!(state.can_undo());
+
+        state.undo_history.expire_paused_actions();
+        assert!(!state.can_undo());
+    }
+}